@@ -1,5 +1,5 @@
-use crate::db::{Database, QueryUtils};
-use crate::experiments::{CrateSelect, Experiment, Mode, PlatformIssue, Status};
+use crate::db::{Database, QueryUtils, Transaction};
+use crate::experiments::{CrateSelect, Experiment, ExperimentKind, Mode, PlatformIssue, Status};
 use crate::prelude::*;
 use crate::toolchain::Toolchain;
 use chrono::Utc;
@@ -9,6 +9,7 @@ use rusqlite::OptionalExtension;
 #[derive(Debug, Clone)]
 pub struct CreateExperiment {
     pub name: String,
+    pub kind: ExperimentKind,
     pub toolchains: [Toolchain; 2],
     pub mode: Mode,
     pub crate_select: CrateSelect,
@@ -76,8 +77,15 @@ impl ExperimentActions for Database {
             anyhow::bail!("experiment '{}' already exists", req.name);
         }
 
-        let toolchain_start = req.toolchains[0].to_string();
-        let toolchain_end = req.toolchains[1].to_string();
+        req.kind.validate(&req.toolchains)?;
+        for toolchain in &req.toolchains {
+            toolchain.check_local()?;
+        }
+        let crate_select = req.crate_select.clone().with_seed();
+        let crates = self.resolve_crates(&crate_select)?;
+
+        let toolchain_start = req.toolchains[0].id();
+        let toolchain_end = req.toolchains[1].id();
         let mode_str = req.mode.to_string();
         let status_str = Status::Queued.to_string();
         let created_at_str = now.to_rfc3339();
@@ -96,11 +104,13 @@ impl ExperimentActions for Database {
 
         conn.execute(
             "INSERT INTO experiments 
-             (name, mode, cap_lints, toolchain_start, toolchain_end, priority, created_at, 
-              platform_issue, platform_issue_url, platform_issue_identifier, status, ignore_blacklist)
-             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+             (name, kind, mode, cap_lints, toolchain_start, toolchain_end, priority, created_at, 
+              platform_issue, platform_issue_url, platform_issue_identifier, status, ignore_blacklist,
+              crate_select)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             rusqlite::params![
                 &req.name,
+                req.kind.to_str(),
                 &mode_str,
                 "warn", // default cap_lints
                 &toolchain_start,
@@ -112,6 +122,7 @@ impl ExperimentActions for Database {
                 platform_issue_identifier,
                 &status_str,
                 0, // ignore_blacklist = false
+                crate_select.to_string(),
             ],
         )
         .context("failed to insert experiment")?;
@@ -132,6 +143,8 @@ impl ExperimentActions for Database {
             .context("failed to insert experiment metadata")?;
         }
 
+        self.set_experiment_crates(&req.name, &crates)?;
+
         // Get and return the created experiment
         self.get(&req.name)?
             .ok_or_else(|| anyhow::anyhow!("failed to retrieve created experiment"))
@@ -160,6 +173,12 @@ impl ExperimentActions for Database {
             );
         }
 
+        let crate_select = req.crate_select.clone().map(CrateSelect::with_seed);
+        let crates = crate_select
+            .as_ref()
+            .map(|select| self.resolve_crates(select))
+            .transpose()?;
+
         // Build update query dynamically
         let mut updates = Vec::new();
         let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
@@ -188,6 +207,14 @@ impl ExperimentActions for Database {
             params.push(Box::new(priority));
         }
 
+        if let Some(ref crate_select) = crate_select {
+            updates.push("crate_select = ?");
+            params.push(Box::new(crate_select.to_string()));
+        }
+
+        // Get the final name (might have changed)
+        let final_name = req.name.as_deref().unwrap_or(name);
+
         if updates.is_empty() {
             // Nothing to update, just return current experiment
             return self
@@ -200,23 +227,41 @@ impl ExperimentActions for Database {
 
         let query = format!("UPDATE experiments SET {} WHERE name = ?", updates.join(", "));
 
+        // The crates must always match the stored `crate_select`: they are
+        // only replaced once the experiment itself was updated.
+        let t = Transaction::new(&conn)?;
+        // A renamed experiment is only consistent again once the rows
+        // referencing it are renamed too.
+        conn.execute_batch("PRAGMA defer_foreign_keys = ON")?;
         conn.execute(
             &query,
             rusqlite::params_from_iter(params.iter().map(|p| p.as_ref())),
         )
         .context("failed to update experiment")?;
 
+        if final_name != name {
+            for table in ["experiment_crates", "experiment_metadata", "results", "shas", "saved_names"] {
+                conn.execute(
+                    &format!("UPDATE {} SET experiment = ? WHERE experiment = ?", table),
+                    rusqlite::params![final_name, name],
+                )
+                .with_context(|| format!("failed to rename the experiment in {}", table))?;
+            }
+        }
+
+        if let Some(crates) = crates {
+            Self::replace_experiment_crates(&t, final_name, &crates)?;
+        }
+
         // Update metadata if callback_url is provided
         if let Some(ref callback_url) = req.callback_url {
             conn.execute(
                 "UPDATE experiment_metadata SET callback_url = ? WHERE experiment = ?",
-                rusqlite::params![callback_url, name],
+                rusqlite::params![callback_url, final_name],
             )
             .context("failed to update experiment metadata")?;
         }
-
-        // Get the final name (might have changed)
-        let final_name = req.name.as_deref().unwrap_or(name);
+        t.commit()?;
 
         self.get(final_name)?
             .ok_or_else(|| anyhow::anyhow!("experiment '{}' not found after edit", final_name))
@@ -257,10 +302,10 @@ impl ExperimentActions for Database {
 
         let result = conn
             .query_row(
-                "SELECT name, mode, cap_lints, toolchain_start, toolchain_end, priority, 
+                "SELECT name, kind, mode, cap_lints, toolchain_start, toolchain_end, priority, 
                         created_at, started_at, completed_at, platform_issue, platform_issue_url, 
                         platform_issue_identifier, status, assigned_to, report_url, 
-                        ignore_blacklist, requirement
+                        ignore_blacklist, requirement, crate_select
                  FROM experiments WHERE name = ?",
                 [name],
                 Database::parse_experiment,
@@ -275,10 +320,10 @@ impl ExperimentActions for Database {
         let conn = self.conn()?;
 
         let experiments = conn.query(
-            "SELECT name, mode, cap_lints, toolchain_start, toolchain_end, priority, 
+            "SELECT name, kind, mode, cap_lints, toolchain_start, toolchain_end, priority, 
                     created_at, started_at, completed_at, platform_issue, platform_issue_url, 
                     platform_issue_identifier, status, assigned_to, report_url, 
-                    ignore_blacklist, requirement
+                    ignore_blacklist, requirement, crate_select
              FROM experiments ORDER BY created_at DESC",
             std::iter::empty::<&dyn rusqlite::ToSql>(),
             Database::parse_experiment,
@@ -311,6 +356,8 @@ impl ExperimentActions for Database {
             );
         }
 
+        self.pin_experiment_shas(name)?;
+
         // Update status to running and set started_at
         conn.execute(
             "UPDATE experiments SET status = ?, started_at = ? WHERE name = ?",
@@ -388,19 +435,19 @@ impl Database {
     /// Returns (completed_count, total_count)
     /// 
     /// Progress is calculated by:
-    /// - completed_count: Number of unique crates that have results in the results table
+    /// - completed_count: Number of non-skipped crates marked complete in the experiment_crates table
     /// - total_count: Number of non-skipped crates in the experiment_crates table
     /// 
-    /// Note: Uses COUNT(DISTINCT crate) to count unique crates with results.
-    /// Each crate may have multiple results (one per toolchain), so DISTINCT is used
-    /// to count each crate only once.
+    /// Note: A crate is only marked complete once it has a result for both
+    /// toolchains, so an interrupted crate isn't counted.
     pub fn get_experiment_progress(&self, experiment_name: &str) -> Fallible<(i64, i64)> {
         let conn = self.conn()?;
         
-        // Get completed count from results table
+        // Get completed count from experiment_crates table
         let completed: i64 = conn
             .query_row(
-                "SELECT COUNT(DISTINCT crate) FROM results WHERE experiment = ?",
+                "SELECT COUNT(*) FROM experiment_crates
+                 WHERE experiment = ? AND skipped = 0 AND status = 'complete'",
                 [experiment_name],
                 |row| row.get(0),
             )
@@ -422,6 +469,7 @@ impl Database {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::crates::{Crate, RegistryCrate};
     use crate::toolchain::RustwideToolchain;
     use std::str::FromStr;
 
@@ -430,6 +478,7 @@ mod tests {
         let db = Database::temp().unwrap();
         let req = CreateExperiment {
             name: "test-exp".to_string(),
+            kind: ExperimentKind::ToolchainComparison,
             toolchains: [
                 Toolchain {
                     source: RustwideToolchain::Dist("stable".to_string()),
@@ -469,6 +518,7 @@ mod tests {
         // Create experiment
         let req = CreateExperiment {
             name: "test-exp".to_string(),
+            kind: ExperimentKind::ToolchainComparison,
             toolchains: [
                 Toolchain::from_str("stable").unwrap(),
                 Toolchain::from_str("beta").unwrap(),
@@ -501,6 +551,49 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_edit_experiment_crates() {
+        let db = Database::temp().unwrap();
+        let req = |name: &str| CreateExperiment {
+            name: name.to_string(),
+            kind: ExperimentKind::ToolchainComparison,
+            toolchains: [
+                Toolchain::from_str("stable").unwrap(),
+                Toolchain::from_str("beta").unwrap(),
+            ],
+            mode: Mode::BuildAndTest,
+            crate_select: CrateSelect::Dummy,
+            platform_issue: None,
+            callback_url: None,
+            priority: 0,
+        };
+        db.create(req("first")).unwrap();
+        db.create(req("second")).unwrap();
+        let crates = db.experiment_crates("first").unwrap();
+
+        let list = CrateSelect::List(["reg:serde-1.0.0".to_string()].into_iter().collect());
+        let edit_req = |name: &str| EditExperiment {
+            name: Some(name.to_string()),
+            crate_select: Some(list.clone()),
+            ..Default::default()
+        };
+
+        // A failed update leaves the crates matching the stored selection.
+        assert!(db.edit("first", edit_req("second")).is_err());
+        let exp = db.get("first").unwrap().unwrap();
+        assert_eq!(exp.crate_select, Some(CrateSelect::Dummy));
+        assert_eq!(db.experiment_crates("first").unwrap(), crates);
+
+        // Renamed experiments get their new crates under their new name.
+        let exp = db.edit("first", edit_req("renamed")).unwrap();
+        assert_eq!(exp.crate_select, Some(list.clone()));
+        assert_eq!(
+            db.experiment_crates("renamed").unwrap(),
+            vec![Crate::Registry(RegistryCrate::new("serde", "1.0.0"))]
+        );
+        assert!(db.experiment_crates("first").unwrap().is_empty());
+    }
+
     #[test]
     fn test_delete_experiment_only_queued() {
         let db = Database::temp().unwrap();
@@ -508,6 +601,7 @@ mod tests {
         // Create experiment
         let req = CreateExperiment {
             name: "test-exp".to_string(),
+            kind: ExperimentKind::ToolchainComparison,
             toolchains: [
                 Toolchain::from_str("stable").unwrap(),
                 Toolchain::from_str("beta").unwrap(),
@@ -536,6 +630,7 @@ mod tests {
         // Create
         let req = CreateExperiment {
             name: "lifecycle-test".to_string(),
+            kind: ExperimentKind::ToolchainComparison,
             toolchains: [
                 Toolchain::from_str("stable").unwrap(),
                 Toolchain::from_str("beta").unwrap(),
@@ -572,6 +667,7 @@ mod tests {
         for i in 0..3 {
             let req = CreateExperiment {
                 name: format!("exp-{}", i),
+                kind: ExperimentKind::ToolchainComparison,
                 toolchains: [
                     Toolchain::from_str("stable").unwrap(),
                     Toolchain::from_str("beta").unwrap(),
@@ -588,4 +684,79 @@ mod tests {
         let experiments = db.list().unwrap();
         assert_eq!(experiments.len(), 3);
     }
+
+    #[test]
+    fn test_create_random_experiment() {
+        use crate::config::{Config, IndexConfig};
+        use crate::crates::index::tests::add_crate;
+
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["libc", "nix", "serde", "tokio"] {
+            add_crate(dir.path(), name, &[("1.0.0", false)]);
+        }
+        let mut config: Config = toml::from_str(include_str!("../../config.toml")).unwrap();
+        config.index = Some(IndexConfig {
+            path: dir.path().to_path_buf(),
+            downloads: None,
+        });
+        let db = Database::temp().unwrap().with_config(config);
+
+        let req = |name: &str, crate_select| CreateExperiment {
+            name: name.to_string(),
+            kind: ExperimentKind::ToolchainComparison,
+            toolchains: [
+                Toolchain::from_str("stable").unwrap(),
+                Toolchain::from_str("beta").unwrap(),
+            ],
+            mode: Mode::BuildOnly,
+            crate_select,
+            platform_issue: None,
+            callback_url: None,
+            priority: 0,
+        };
+        let exp = db
+            .create(req("first", CrateSelect::Random { count: 2, seed: None }))
+            .unwrap();
+        let crate_select = exp.crate_select.unwrap();
+        assert!(matches!(crate_select, CrateSelect::Random { seed: Some(_), .. }));
+
+        // The stored selection re-creates the same experiment.
+        db.create(req("second", crate_select)).unwrap();
+        let crates = db.experiment_crates("first").unwrap();
+        assert_eq!(crates.len(), 2);
+        assert_eq!(db.experiment_crates("second").unwrap(), crates);
+    }
+
+    #[test]
+    fn test_create_cross_target_experiment() {
+        let db = Database::temp().unwrap();
+        let req = |name: &str, end: &str| CreateExperiment {
+            name: name.to_string(),
+            kind: ExperimentKind::CrossTarget,
+            toolchains: [
+                Toolchain::from_str("stable").unwrap(),
+                Toolchain::from_str(end).unwrap(),
+            ],
+            mode: Mode::BuildOnly,
+            crate_select: CrateSelect::Demo,
+            platform_issue: None,
+            callback_url: None,
+            priority: 0,
+        };
+
+        let exp = db
+            .create(req("cross", "stable+target=aarch64-unknown-linux-ohos"))
+            .unwrap();
+        assert_eq!(exp.kind, ExperimentKind::CrossTarget);
+        assert_eq!(
+            exp.toolchains[1].target.as_deref(),
+            Some("aarch64-unknown-linux-ohos")
+        );
+
+        // Invalid experiments are rejected before being stored.
+        assert!(db
+            .create(req("invalid", "beta+target=aarch64-unknown-linux-ohos"))
+            .is_err());
+        assert!(db.get("invalid").unwrap().is_none());
+    }
 }
@@ -1,8 +1,10 @@
 use crate::actions::experiments::{CreateExperiment, EditExperiment, ExperimentActions};
 use crate::api::error::ApiError;
 use crate::api::response::ApiResponse;
+use crate::crates::lists::InvalidCrateSpecs;
 use crate::db::Database;
-use crate::experiments::{CrateSelect, Experiment, Mode, PlatformIssue};
+use crate::experiments::{CrateSelect, Experiment, ExperimentKind, Mode, PlatformIssue};
+use crate::toolchain::UnusableToolchain;
 use axum::{
     extract::{Path, State},
     Json,
@@ -13,6 +15,8 @@ use std::sync::Arc;
 #[derive(Debug, Deserialize)]
 pub struct CreateExperimentRequest {
     pub name: String,
+    #[serde(default)]
+    pub kind: Option<String>,
     pub toolchains: [String; 2],
     pub mode: String,
     pub crate_select: String,
@@ -42,6 +46,20 @@ pub struct ExperimentListResponse {
     pub experiments: Vec<Experiment>,
 }
 
+/// Parse a crate selection received by the API. `file:` selections read a
+/// path on the server, so they are only accepted from the CLI.
+fn parse_crate_select(spec: &str) -> Result<CrateSelect, ApiError> {
+    let crate_select = spec
+        .parse()
+        .map_err(|e: anyhow::Error| ApiError::BadRequest(e.to_string()))?;
+    if let CrateSelect::File(_) = crate_select {
+        return Err(ApiError::BadRequest(
+            "crate selection 'file:' is only available from the CLI".to_string(),
+        ));
+    }
+    Ok(crate_select)
+}
+
 /// POST /api/v1/experiments - Create experiment
 pub async fn create_experiment(
     State(db): State<Arc<Database>>,
@@ -57,6 +75,16 @@ pub async fn create_experiment(
             .map_err(|e: anyhow::Error| ApiError::BadRequest(e.to_string()))?,
     ];
 
+    // Parse kind
+    let kind: ExperimentKind = req
+        .kind
+        .as_deref()
+        .unwrap_or(ExperimentKind::ToolchainComparison.to_str())
+        .parse()
+        .map_err(|e: anyhow::Error| ApiError::BadRequest(e.to_string()))?;
+    kind.validate(&toolchains)
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
     // Parse mode
     let mode: Mode = req
         .mode
@@ -64,13 +92,11 @@ pub async fn create_experiment(
         .map_err(|e: anyhow::Error| ApiError::BadRequest(e.to_string()))?;
 
     // Parse crate_select
-    let crate_select: CrateSelect = req
-        .crate_select
-        .parse()
-        .map_err(|e: anyhow::Error| ApiError::BadRequest(e.to_string()))?;
+    let crate_select = parse_crate_select(&req.crate_select)?;
 
     let create_req = CreateExperiment {
         name: req.name,
+        kind,
         toolchains,
         mode,
         crate_select,
@@ -82,7 +108,11 @@ pub async fn create_experiment(
     let experiment = db
         .create(create_req)
         .map_err(|e| {
-            if e.to_string().contains("already exists") {
+            if e.downcast_ref::<InvalidCrateSpecs>().is_some()
+                || e.downcast_ref::<UnusableToolchain>().is_some()
+            {
+                ApiError::BadRequest(e.to_string())
+            } else if e.to_string().contains("already exists") {
                 ApiError::Conflict(e.to_string())
             } else {
                 ApiError::InternalServerError(e.to_string())
@@ -130,9 +160,9 @@ pub async fn edit_experiment(
 
     let crate_select = req
         .crate_select
-        .map(|cs| cs.parse())
-        .transpose()
-        .map_err(|e: anyhow::Error| ApiError::BadRequest(e.to_string()))?;
+        .as_deref()
+        .map(parse_crate_select)
+        .transpose()?;
 
     let edit_req = EditExperiment {
         name: req.name,
@@ -146,7 +176,9 @@ pub async fn edit_experiment(
     let experiment = db
         .edit(&name, edit_req)
         .map_err(|e| {
-            if e.to_string().contains("not found") {
+            if e.downcast_ref::<InvalidCrateSpecs>().is_some() {
+                ApiError::BadRequest(e.to_string())
+            } else if e.to_string().contains("not found") {
                 ApiError::NotFound(e.to_string())
             } else if e.to_string().contains("only be edited in 'queued' status") {
                 ApiError::BadRequest(e.to_string())
@@ -181,7 +213,11 @@ pub async fn run_experiment(
     State(db): State<Arc<Database>>,
     Path(name): Path<String>,
 ) -> Result<Json<ApiResponse<()>>, ApiError> {
-    db.run(&name).map_err(|e| {
+    // Pinning the git crates queries their repositories.
+    let res = tokio::task::spawn_blocking(move || db.run(&name))
+        .await
+        .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+    res.map_err(|e| {
         if e.to_string().contains("not found") {
             ApiError::NotFound(e.to_string())
         } else {
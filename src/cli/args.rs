@@ -11,8 +11,16 @@ pub struct Cli {
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
-    /// Prepare local environment
-    PrepareLocal,
+    /// Prepare local environment, prefetching the crates of an experiment
+    PrepareLocal {
+        /// Experiment whose crates are downloaded into the workspace cache
+        #[arg(long = "ex")]
+        name: Option<String>,
+
+        /// Config file path
+        #[arg(long = "config", default_value = "config.toml")]
+        config: String,
+    },
 
     /// Define an experiment
     DefineEx {
@@ -34,9 +42,17 @@ pub enum Commands {
         #[arg(long = "mode", default_value = "build-and-test")]
         mode: String,
 
+        /// Experiment kind (default: toolchain-comparison)
+        #[arg(long = "kind", default_value = "toolchain-comparison")]
+        kind: String,
+
         /// Priority (default: 0)
         #[arg(long = "priority", default_value = "0")]
         priority: i32,
+
+        /// Config file path, needed by the selections from the crates.io index
+        #[arg(long = "config", default_value = "config.toml")]
+        config: String,
     },
 
     /// Run experiment
@@ -48,6 +64,10 @@ pub enum Commands {
         /// Number of threads
         #[arg(short = 't', long = "threads", default_value = "1")]
         threads: usize,
+
+        /// Config file path
+        #[arg(long = "config", default_value = "config.toml")]
+        config: String,
     },
 
     /// Generate report
@@ -1,9 +1,10 @@
 use crate::actions::experiments::{CreateExperiment, ExperimentActions};
 use crate::db::Database;
-use crate::experiments::{CrateSelect, Mode};
+use crate::experiments::{CrateSelect, ExperimentKind, Mode};
 use crate::prelude::*;
 use crate::toolchain::Toolchain;
 
+#[allow(clippy::too_many_arguments)]
 pub fn define_ex(
     db: &Database,
     name: String,
@@ -11,6 +12,7 @@ pub fn define_ex(
     toolchain2: String,
     crate_select: String,
     mode: String,
+    kind: String,
     priority: i32,
 ) -> Fallible<()> {
     println!("Defining experiment '{}'...", name);
@@ -22,11 +24,15 @@ pub fn define_ex(
     // Parse mode
     let mode: Mode = mode.parse()?;
 
+    // Parse experiment kind
+    let kind: ExperimentKind = kind.parse()?;
+
     // Parse crate selection
     let crate_select: CrateSelect = crate_select.parse()?;
 
     let req = CreateExperiment {
         name: name.clone(),
+        kind,
         toolchains: [tc1, tc2],
         mode,
         crate_select,
@@ -39,6 +45,7 @@ pub fn define_ex(
     
     println!("✓ Experiment '{}' created successfully", experiment.name);
     println!("  Status: {:?}", experiment.status);
+    println!("  Kind: {}", experiment.kind);
     println!("  Mode: {:?}", experiment.mode);
     println!("  Toolchains: {} vs {}", 
         experiment.toolchains[0], 
@@ -1,15 +1,38 @@
+use crate::config::Config;
+use crate::db::Database;
 use crate::prelude::*;
+use crate::runner::{prefetch, Workspace};
 
-pub fn prepare_local() -> Fallible<()> {
+pub fn prepare_local(name: Option<String>, config: String) -> Fallible<()> {
     println!("Preparing local environment...");
-    
-    // TODO: Implement environment preparation logic
-    // This would typically:
-    // 1. Check for required tools (cargo, rustup, etc.)
-    // 2. Create necessary directories
-    // 3. Initialize database if needed
-    // 4. Download any required resources
-    
+
+    let workspace = Workspace::new(&*WORK_DIR)?;
+
+    if let Some(name) = name {
+        let config = Config::load(&config)?;
+        let db = Database::open()?.with_config(config.clone());
+
+        // Pin git crates first so the cached mirrors hold the commits that
+        // will actually be built.
+        db.pin_experiment_shas(&name)?;
+        let crates = db.experiment_crates(&name)?;
+        println!("Prefetching {} crate(s) of experiment '{}'...", crates.len(), name);
+
+        let mut failed = 0;
+        for krate in &crates {
+            match prefetch(&workspace, &config, krate) {
+                Ok(()) => println!("  ✓ {}", krate),
+                Err(err) => {
+                    println!("  ✗ {}: {:#}", krate, err);
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            anyhow::bail!("failed to prefetch {} of {} crate(s)", failed, crates.len());
+        }
+    }
+
     println!("✓ Environment prepared successfully");
     Ok(())
 }
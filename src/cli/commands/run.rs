@@ -1,8 +1,11 @@
 use crate::actions::experiments::ExperimentActions;
+use crate::config::Config;
 use crate::db::Database;
+use crate::experiments::Status;
 use crate::prelude::*;
+use crate::runner::{run_ex, Workspace};
 
-pub fn run_graph(db: &Database, name: String, threads: usize) -> Fallible<()> {
+pub fn run_graph(db: &Database, config: &Config, name: String, threads: usize) -> Fallible<()> {
     println!("Running experiment '{}' with {} threads...", name, threads);
 
     // Check if experiment exists
@@ -13,11 +16,28 @@ pub fn run_graph(db: &Database, name: String, threads: usize) -> Fallible<()> {
     println!("  Mode: {:?}", experiment.mode);
     println!("  Status: {:?}", experiment.status);
 
-    // Update status to running
-    db.run(&name)?;
+    match experiment.status {
+        // Update status to running
+        Status::Queued => db.run(&name)?,
+        // Resume an interrupted run: the crates it was testing start over.
+        Status::Running => {
+            let requeued = db.requeue_interrupted_crates(&name)?;
+            println!("  Resuming, {} interrupted crate(s) requeued", requeued);
+        }
+        status => anyhow::bail!(
+            "can only run experiments in 'queued' or 'running' state, current state: {}",
+            status
+        ),
+    }
 
-    println!("✓ Experiment '{}' started", name);
-    println!("  Note: Actual execution is not yet implemented (Phase 3 integration pending)");
+    let workspace = Workspace::new(&*WORK_DIR)?;
+    run_ex(&experiment, &workspace, db, threads, config, &|| {
+        db.next_crate(&name)
+    })?;
+    db.complete(&name)?;
+
+    let (completed, total) = db.get_experiment_progress(&name)?;
+    println!("✓ Experiment '{}' finished ({}/{} crates)", name, completed, total);
 
     Ok(())
 }
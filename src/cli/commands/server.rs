@@ -9,11 +9,11 @@ pub async fn server(port: u16, config_path: String) -> Fallible<()> {
     println!("  Config: {}", config_path);
 
     // Load configuration
-    let _config = crate::config::Config::load(&config_path)
+    let config = crate::config::Config::load(&config_path)
         .context("Failed to load configuration")?;
 
     // Initialize database
-    let db = Database::open()?;
+    let db = Database::open()?.with_config(config);
     let db = Arc::new(db);
 
     // Build router
@@ -1,6 +1,7 @@
 pub mod args;
 pub mod commands;
 
+use crate::config::Config;
 use crate::db::Database;
 use crate::prelude::*;
 use args::{Cli, Commands};
@@ -10,7 +11,9 @@ pub async fn run() -> Fallible<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::PrepareLocal => commands::prepare::prepare_local(),
+        Commands::PrepareLocal { name, config } => {
+            commands::prepare::prepare_local(name, config)
+        }
         
         Commands::DefineEx {
             name,
@@ -18,9 +21,16 @@ pub async fn run() -> Fallible<()> {
             toolchain2,
             crate_select,
             mode,
+            kind,
             priority,
+            config,
         } => {
-            let db = Database::open()?;
+            // The configuration is optional unless the selection needs it.
+            let db = if std::path::Path::new(&config).exists() {
+                Database::open()?.with_config(Config::load(&config)?)
+            } else {
+                Database::open()?
+            };
             commands::define::define_ex(
                 &db,
                 name,
@@ -28,13 +38,19 @@ pub async fn run() -> Fallible<()> {
                 toolchain2,
                 crate_select,
                 mode,
+                kind,
                 priority,
             )
         }
         
-        Commands::RunGraph { name, threads } => {
-            let db = Database::open()?;
-            commands::run::run_graph(&db, name, threads)
+        Commands::RunGraph {
+            name,
+            threads,
+            config,
+        } => {
+            let config = Config::load(&config)?;
+            let db = Database::open()?.with_config(config.clone());
+            commands::run::run_graph(&db, &config, name, threads)
         }
         
         Commands::GenReport { name, output_dir } => {
@@ -1,17 +1,40 @@
 use crate::prelude::*;
 use crate::utils::size::Size;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Config {
     pub demo_crates: DemoCrates,
+    /// 本地 crates.io 索引，full / top / random 选择从中解析 crate
+    #[serde(default)]
+    pub index: Option<IndexConfig>,
+    /// local 选择扫描的本地 crate 目录，默认为 `local-crates`
+    #[serde(default)]
+    pub local_crates_dir: Option<PathBuf>,
     pub sandbox: SandboxConfig,
     pub server: ServerConfig,
     #[serde(default)]
     pub platforms: PlatformsConfig,
+    #[serde(default)]
+    pub ohos: Option<OhosConfig>,
+    /// 各目标平台运行测试二进制所用的 runner（例如 qemu-aarch64 包装脚本），
+    /// 会以 `CARGO_TARGET_<TRIPLE>_RUNNER` 传给 cargo
+    #[serde(default)]
+    pub target_runners: HashMap<String, String>,
+    /// 在设备上（或其替身上）执行交叉编译测试二进制的执行器，优先于 runner
+    #[serde(default)]
+    pub test_executors: HashMap<String, TestExecutorConfig>,
+    /// 离线模式：只使用工作目录缓存中的 crate 与 git 镜像，缺失的源直接判定为
+    /// broken，不访问网络
+    #[serde(default)]
+    pub offline: bool,
+    #[serde(default)]
+    pub toolchains: ToolchainsConfig,
 }
 
 impl Config {
@@ -22,23 +45,68 @@ impl Config {
     }
 }
 
+/// Crates tested by the `demo` selection.
 #[derive(Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "kebab-case")]
 pub struct DemoCrates {
+    /// crates.io 上的 crate，`name-version` 固定版本，仅写名称时使用索引中的最新版本
     #[serde(default)]
     pub crates: Vec<String>,
+    /// GitHub 仓库，格式为 `org/repo`
     #[serde(default)]
     pub github_repos: Vec<String>,
+    /// Gitee 仓库，格式为 `org/repo`
+    #[serde(default)]
+    pub gitee_repos: Vec<String>,
+    /// 本地 crate 目录（位于 `local-crates-dir` 下）的名称
     #[serde(default)]
     pub local_crates: Vec<String>,
 }
 
+/// Local copy of the crates.io index, the only source of the full crate list
+/// available to offline builders.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct IndexConfig {
+    /// crates.io-index 的本地克隆（或 sparse 索引镜像）目录
+    pub path: PathBuf,
+    /// crates.io 数据库导出中的 `crates.csv`，top 选择按其下载量排序
+    #[serde(default)]
+    pub downloads: Option<PathBuf>,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct SandboxConfig {
     pub memory_limit: Size,
     pub build_log_max_size: Size,
     pub build_log_max_lines: u64,
+    /// 单条构建命令的超时时间（秒）
+    #[serde(default)]
+    pub build_timeout_secs: Option<u64>,
+    /// 用于限制内存的 cgroup v2 目录，未设置时退回到 RLIMIT_AS
+    #[serde(default)]
+    pub cgroup_root: Option<PathBuf>,
+    /// 构建与测试的隔离方式
+    #[serde(default)]
+    pub backend: Option<SandboxBackend>,
+}
+
+string_enum! {
+    pub enum SandboxBackend {
+        Process => "process",
+        Namespace => "namespace",
+    }
+}
+
+impl SandboxConfig {
+    pub fn build_timeout(&self) -> Duration {
+        Duration::from_secs(self.build_timeout_secs.unwrap_or(15 * 60))
+    }
+
+    pub fn backend(&self) -> SandboxBackend {
+        self.backend.unwrap_or(SandboxBackend::Process)
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -76,6 +144,54 @@ impl CallbackConfig {
     }
 }
 
+/// Installation of the toolchains used by experiments.
+#[derive(Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct ToolchainsConfig {
+    /// 工具链下载镜像的根地址，替代 static.rust-lang.org（`dist/` 发布渠道）与
+    /// ci-artifacts.rust-lang.org（`rustc-builds/` CI 构建），可指向本地文件服务器
+    #[serde(default)]
+    pub mirror: Option<String>,
+}
+
+/// OpenHarmony SDK used to cross-compile for the `*-linux-ohos` targets
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct OhosConfig {
+    /// OpenHarmony SDK 路径（包含 `native/` 目录的那一层）
+    pub sdk_path: PathBuf,
+}
+
+impl OhosConfig {
+    pub fn native_dir(&self) -> PathBuf {
+        self.sdk_path.join("native")
+    }
+
+    pub fn clang(&self) -> PathBuf {
+        self.native_dir().join("llvm/bin/clang")
+    }
+
+    pub fn clangxx(&self) -> PathBuf {
+        self.native_dir().join("llvm/bin/clang++")
+    }
+
+    pub fn llvm_ar(&self) -> PathBuf {
+        self.native_dir().join("llvm/bin/llvm-ar")
+    }
+
+    pub fn sysroot(&self) -> PathBuf {
+        self.native_dir().join("sysroot")
+    }
+}
+
+/// Where the test binaries of a cross target are executed
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum TestExecutorConfig {
+    /// 复制到本地目录后直接执行（用于 CI）
+    Local { dir: PathBuf },
+}
+
 /// Platform configuration
 #[derive(Clone, Serialize, Deserialize)]
 pub struct PlatformInstanceConfig {
@@ -117,4 +233,84 @@ mod tests {
         assert_eq!(config.timeout_secs(), 60);
         assert_eq!(config.retry_count(), 5);
     }
+
+    #[test]
+    fn test_ohos_config() {
+        let config: Config = toml::from_str(include_str!("../config.toml")).unwrap();
+        assert!(config.ohos.is_none());
+
+        let config: OhosConfig = toml::from_str("sdk-path = \"/opt/ohos-sdk/linux\"").unwrap();
+        assert_eq!(
+            config.clang(),
+            PathBuf::from("/opt/ohos-sdk/linux/native/llvm/bin/clang")
+        );
+        assert_eq!(
+            config.sysroot(),
+            PathBuf::from("/opt/ohos-sdk/linux/native/sysroot")
+        );
+    }
+
+    #[test]
+    fn test_index_config() {
+        let config: Config = toml::from_str(include_str!("../config.toml")).unwrap();
+        assert!(config.index.is_none());
+
+        let mut content = include_str!("../config.toml").to_string();
+        content.push_str("\n[index]\npath = \"/srv/crates.io-index\"\n");
+        let config: Config = toml::from_str(&content).unwrap();
+        let index = config.index.unwrap();
+        assert_eq!(index.path, PathBuf::from("/srv/crates.io-index"));
+        assert!(index.downloads.is_none());
+    }
+
+    #[test]
+    fn test_target_runners() {
+        let config: Config = toml::from_str(include_str!("../config.toml")).unwrap();
+        assert!(config.target_runners.is_empty());
+
+        let mut content = include_str!("../config.toml").to_string();
+        content.push_str(
+            "\n[target-runners]\naarch64-unknown-linux-ohos = \"qemu-aarch64 -L /opt/sysroot\"\n",
+        );
+        let config: Config = toml::from_str(&content).unwrap();
+        assert_eq!(
+            config.target_runners["aarch64-unknown-linux-ohos"],
+            "qemu-aarch64 -L /opt/sysroot"
+        );
+    }
+
+    #[test]
+    fn test_test_executors() {
+        let mut content = include_str!("../config.toml").to_string();
+        content.push_str(
+            "\n[test-executors.aarch64-unknown-linux-ohos]\nkind = \"local\"\ndir = \"/tmp/device\"\n",
+        );
+        let config: Config = toml::from_str(&content).unwrap();
+        match &config.test_executors["aarch64-unknown-linux-ohos"] {
+            TestExecutorConfig::Local { dir } => assert_eq!(dir, &PathBuf::from("/tmp/device")),
+        }
+    }
+
+    #[test]
+    fn test_sandbox_config_build_timeout() {
+        let config: Config = toml::from_str(include_str!("../config.toml")).unwrap();
+        assert_eq!(config.sandbox.build_timeout(), Duration::from_secs(900));
+        assert!(config.sandbox.cgroup_root.is_none());
+        assert_eq!(config.sandbox.backend(), SandboxBackend::Process);
+
+        let config: SandboxConfig = toml::from_str(
+            r#"
+                memory-limit = { "GIGABYTES" = 1 }
+                build-log-max-size = { "MEGABYTES" = 1 }
+                build-log-max-lines = 10
+                build-timeout-secs = 60
+                cgroup-root = "/sys/fs/cgroup/crater"
+                backend = "namespace"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.build_timeout(), Duration::from_secs(60));
+        assert_eq!(config.cgroup_root, Some(PathBuf::from("/sys/fs/cgroup/crater")));
+        assert_eq!(config.backend(), SandboxBackend::Namespace);
+    }
 }
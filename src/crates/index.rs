@@ -0,0 +1,442 @@
+use crate::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A local checkout of the crates.io index, either the git repository or a
+/// directory mirroring the sparse index: both share the same file layout,
+/// one file per crate with a JSON line per published version.
+pub struct Index {
+    path: PathBuf,
+}
+
+/// Every published version of a crate.
+pub struct IndexCrate {
+    pub name: String,
+    pub versions: Vec<IndexVersion>,
+}
+
+#[derive(Deserialize)]
+pub struct IndexVersion {
+    #[serde(rename = "vers")]
+    pub num: String,
+    #[serde(default)]
+    pub yanked: bool,
+    #[serde(default)]
+    pub deps: Vec<IndexDependency>,
+    /// SHA-256 of the `.crate` archive, hex encoded.
+    #[serde(default)]
+    pub cksum: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct IndexDependency {
+    /// Name of the dependency in the manifest, which may be a rename.
+    pub name: String,
+    /// Name of the actual crate when the dependency is renamed.
+    #[serde(default)]
+    pub package: Option<String>,
+    /// `normal`, `build` or `dev`; old entries leave it out for `normal`.
+    #[serde(default)]
+    pub kind: Option<String>,
+}
+
+impl IndexDependency {
+    pub fn crate_name(&self) -> &str {
+        self.package.as_deref().unwrap_or(&self.name)
+    }
+
+    pub fn is_dev(&self) -> bool {
+        self.kind.as_deref() == Some("dev")
+    }
+}
+
+/// The `config.json` at the root of the index.
+#[derive(Deserialize)]
+struct RegistryConfig {
+    /// Where the `.crate` archives are downloaded from.
+    #[serde(default)]
+    dl: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IndexLine {
+    name: String,
+    #[serde(flatten)]
+    version: IndexVersion,
+}
+
+impl Index {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Index { path: path.into() }
+    }
+
+    /// All the crates of the index, sorted by name.
+    pub fn crates(&self) -> Fallible<Vec<IndexCrate>> {
+        if !self.path.is_dir() {
+            anyhow::bail!("crates.io index not found at {}", self.path.display());
+        }
+
+        let mut crates = Vec::new();
+        let mut dirs = vec![self.path.clone()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(&dir)? {
+                let entry = entry?;
+                let file_name = entry.file_name();
+                let file_name = file_name.to_string_lossy();
+                // Skip `.git` and other hidden files.
+                if file_name.starts_with('.') {
+                    continue;
+                }
+
+                let path = entry.path();
+                if entry.file_type()?.is_dir() {
+                    dirs.push(path);
+                } else if !is_crate_file(path.strip_prefix(&self.path)?) {
+                    // `config.json`, READMEs and scripts of mirrors...
+                    continue;
+                } else if let Some(krate) = parse_crate_file(&path)? {
+                    crates.push(krate);
+                }
+            }
+        }
+
+        crates.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(crates)
+    }
+
+    /// The versions of a single crate, or `None` if it isn't in the index.
+    pub fn krate(&self, name: &str) -> Fallible<Option<IndexCrate>> {
+        let path = self.path.join(crate_path(&name.to_lowercase()));
+        if !path.is_file() {
+            return Ok(None);
+        }
+        parse_crate_file(&path)
+    }
+
+    /// The `dl` setting of the registry, or `None` if the index doesn't
+    /// have a `config.json`.
+    pub fn dl(&self) -> Fallible<Option<String>> {
+        let path = self.path.join("config.json");
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let config: RegistryConfig = serde_json::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("invalid registry configuration {}", path.display()))?;
+        Ok(config.dl)
+    }
+}
+
+/// URL of the `.crate` archive of a crate, expanding the `dl` setting of the
+/// registry the way cargo does: its markers are replaced if it has any,
+/// otherwise `/<name>/<version>/download` is appended.
+pub fn download_url(dl: &str, name: &str, version: &str, cksum: Option<&str>) -> String {
+    const MARKERS: &[&str] = &[
+        "{crate}",
+        "{version}",
+        "{prefix}",
+        "{lowerprefix}",
+        "{sha256-checksum}",
+    ];
+    if !MARKERS.iter().any(|marker| dl.contains(marker)) {
+        return format!("{}/{}/{}/download", dl.trim_end_matches('/'), name, version);
+    }
+    dl.replace("{crate}", name)
+        .replace("{version}", version)
+        .replace("{prefix}", &crate_prefix(name))
+        .replace("{lowerprefix}", &crate_prefix(&name.to_lowercase()))
+        .replace("{sha256-checksum}", cksum.unwrap_or_default())
+}
+
+/// Directory of the file of crate `name` in the index, relative to its root.
+fn crate_prefix(name: &str) -> String {
+    match name.len() {
+        1 => "1".to_string(),
+        2 => "2".to_string(),
+        3 => format!("3/{}", &name[..1]),
+        _ => format!("{}/{}", &name[..2], &name[2..4]),
+    }
+}
+
+/// Location of the file of crate `name` in the index, relative to its root.
+fn crate_path(name: &str) -> PathBuf {
+    Path::new(&crate_prefix(name)).join(name)
+}
+
+/// Whether `path`, relative to the root of the index, is the location of the
+/// file of a crate.
+fn is_crate_file(path: &Path) -> bool {
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) if name.is_ascii() => path == crate_path(name),
+        _ => false,
+    }
+}
+
+fn parse_crate_file(path: &Path) -> Fallible<Option<IndexCrate>> {
+    let content = fs::read_to_string(path)?;
+    let mut name = None;
+    let mut versions = Vec::new();
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        let line: IndexLine = serde_json::from_str(line)
+            .with_context(|| format!("invalid index entry in {}", path.display()))?;
+        name.get_or_insert(line.name);
+        versions.push(line.version);
+    }
+    Ok(name.map(|name| IndexCrate { name, versions }))
+}
+
+impl IndexCrate {
+    /// The version crater should test: the highest one that isn't yanked,
+    /// preferring stable releases over pre-releases.
+    pub fn latest_version(&self) -> Option<&str> {
+        self.latest().map(|version| version.num.as_str())
+    }
+
+    /// The entry of [`latest_version`](IndexCrate::latest_version).
+    pub fn latest(&self) -> Option<&IndexVersion> {
+        self.versions
+            .iter()
+            .filter(|version| !version.yanked)
+            .filter_map(|version| {
+                semver::Version::parse(&version.num)
+                    .ok()
+                    .map(|parsed| (parsed.pre.is_empty(), parsed, version))
+            })
+            .max_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)))
+            .map(|(_, _, version)| version)
+    }
+}
+
+/// Load the download counts of every crate from the `crates.csv` file of
+/// the crates.io database dump.
+pub fn load_downloads(path: &Path) -> Fallible<HashMap<String, u64>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("failed to read downloads from {}", path.display()))?;
+    let mut records = parse_csv(&content).into_iter();
+    let header = records
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("empty downloads file {}", path.display()))?;
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|column| column == name)
+            .ok_or_else(|| anyhow::anyhow!("missing column '{}' in {}", name, path.display()))
+    };
+    let (name, downloads) = (column("name")?, column("downloads")?);
+
+    let mut result = HashMap::new();
+    for record in records {
+        if let (Some(krate), Some(count)) = (record.get(name), record.get(downloads)) {
+            let count = count
+                .parse()
+                .with_context(|| format!("invalid download count for {}: {}", krate, count))?;
+            result.insert(krate.clone(), count);
+        }
+    }
+    Ok(result)
+}
+
+/// Split CSV content into records, handling quoted fields, which may contain
+/// commas, escaped quotes and newlines.
+fn parse_csv(content: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// Write a crate file at its location in the index layout.
+    pub(crate) fn add_crate(index: &Path, name: &str, versions: &[(&str, bool)]) {
+        let versions = versions
+            .iter()
+            .map(|(num, yanked)| (*num, *yanked, &[][..]))
+            .collect::<Vec<_>>();
+        add_crate_with_deps(index, name, &versions);
+    }
+
+    /// A version number, whether it's yanked, and its `(name, kind)` deps.
+    pub(crate) type TestVersion<'a> = (&'a str, bool, &'a [(&'a str, &'a str)]);
+
+    /// Write a crate whose versions depend on other crates.
+    pub(crate) fn add_crate_with_deps(index: &Path, name: &str, versions: &[TestVersion]) {
+        let path = index.join(crate_path(name));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let content = versions
+            .iter()
+            .map(|(num, yanked, deps)| {
+                let deps = deps
+                    .iter()
+                    .map(|(dep, kind)| {
+                        format!(
+                            "{{\"name\":\"{}\",\"req\":\"*\",\"kind\":\"{}\"}}",
+                            dep, kind
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!(
+                    "{{\"name\":\"{}\",\"vers\":\"{}\",\"deps\":[{}],\"cksum\":\"\",\"features\":{{}},\"yanked\":{}}}\n",
+                    name, num, deps, yanked
+                )
+            })
+            .collect::<String>();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_index_crates() {
+        let dir = tempdir().unwrap();
+        add_crate(dir.path(), "serde", &[("1.0.0", false), ("1.0.1", false)]);
+        add_crate(dir.path(), "cc", &[("1.0.0", false)]);
+        fs::write(dir.path().join("config.json"), "{}").unwrap();
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        fs::write(dir.path().join(".git/HEAD"), "ref: refs/heads/master").unwrap();
+        // Files outside of the crate layout aren't crates.
+        fs::write(dir.path().join("README.md"), "# Index mirror\n").unwrap();
+        fs::create_dir_all(dir.path().join("scripts")).unwrap();
+        fs::write(dir.path().join("scripts/update.sh"), "git pull\n").unwrap();
+        fs::write(dir.path().join("se/rd/LICENSE"), "MIT\n").unwrap();
+
+        let crates = Index::new(dir.path()).crates().unwrap();
+        let names = crates.iter().map(|c| c.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["cc", "serde"]);
+        assert_eq!(crates[1].versions.len(), 2);
+
+        assert!(Index::new(dir.path().join("missing")).crates().is_err());
+    }
+
+    #[test]
+    fn test_index_krate() {
+        let dir = tempdir().unwrap();
+        add_crate(dir.path(), "serde", &[("1.0.0", false), ("1.0.1", false)]);
+        add_crate(dir.path(), "cc", &[("1.0.0", false)]);
+
+        let index = Index::new(dir.path());
+        assert_eq!(index.krate("serde").unwrap().unwrap().versions.len(), 2);
+        assert_eq!(index.krate("cc").unwrap().unwrap().name, "cc");
+        assert!(index.krate("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_index_dl() {
+        let dir = tempdir().unwrap();
+        let index = Index::new(dir.path());
+        assert_eq!(index.dl().unwrap(), None);
+
+        fs::write(dir.path().join("config.json"), "{}").unwrap();
+        assert_eq!(index.dl().unwrap(), None);
+
+        fs::write(
+            dir.path().join("config.json"),
+            r#"{"dl":"https://mirror.example.com/crates","api":"https://crates.io"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            index.dl().unwrap().as_deref(),
+            Some("https://mirror.example.com/crates")
+        );
+    }
+
+    #[test]
+    fn test_download_url() {
+        assert_eq!(
+            download_url("https://static.crates.io/crates/", "serde", "1.0.0", None),
+            "https://static.crates.io/crates/serde/1.0.0/download"
+        );
+        assert_eq!(
+            download_url(
+                "https://mirror.example.com/{prefix}/{lowerprefix}/{crate}-{version}.crate?{sha256-checksum}",
+                "Inflector",
+                "0.11.4",
+                Some("abcd"),
+            ),
+            "https://mirror.example.com/In/fl/in/fl/Inflector-0.11.4.crate?abcd"
+        );
+        assert_eq!(
+            download_url("https://mirror.example.com/{prefix}/{crate}", "cc", "1.0.0", None),
+            "https://mirror.example.com/2/cc"
+        );
+    }
+
+    #[test]
+    fn test_latest_version() {
+        let krate = |versions: &[(&str, bool)]| IndexCrate {
+            name: "foo".into(),
+            versions: versions
+                .iter()
+                .map(|(num, yanked)| IndexVersion {
+                    num: num.to_string(),
+                    yanked: *yanked,
+                    deps: Vec::new(),
+                    cksum: None,
+                })
+                .collect(),
+        };
+
+        assert_eq!(
+            krate(&[("0.9.0", false), ("0.10.0", false), ("0.11.0", true)]).latest_version(),
+            Some("0.10.0")
+        );
+        assert_eq!(
+            krate(&[("1.0.0", false), ("2.0.0-alpha.1", false)]).latest_version(),
+            Some("1.0.0")
+        );
+        assert_eq!(
+            krate(&[("2.0.0-rc.1", false)]).latest_version(),
+            Some("2.0.0-rc.1")
+        );
+        assert_eq!(krate(&[("1.0.0", true)]).latest_version(), None);
+    }
+
+    #[test]
+    fn test_load_downloads() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("crates.csv");
+        fs::write(
+            &path,
+            "created_at,description,downloads,id,name\n\
+             2015-01-01,\"A \"\"fast\"\", multi-line\ndescription\",1200,1,serde\n\
+             2016-01-01,plain,42,2,cc\n",
+        )
+        .unwrap();
+
+        let downloads = load_downloads(&path).unwrap();
+        assert_eq!(downloads.len(), 2);
+        assert_eq!(downloads["serde"], 1200);
+        assert_eq!(downloads["cc"], 42);
+
+        fs::write(&path, "name,id\nserde,1\n").unwrap();
+        assert!(load_downloads(&path).is_err());
+    }
+}
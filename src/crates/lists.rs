@@ -2,7 +2,32 @@
 #![allow(dead_code)]
 
 use crate::prelude::*;
-use crate::crates::Crate;
+use crate::config::{Config, IndexConfig};
+use crate::crates::index::{self, Index};
+use crate::crates::{Crate, GitHubRepo, GiteeRepo, RegistryCrate};
+use crate::experiments::CrateSelect;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Entries of a `list:` or `file:` selection that aren't valid crate specs.
+#[derive(Debug, thiserror::Error)]
+#[error("invalid crate specs: {}", format_invalid(.0))]
+pub struct InvalidCrateSpecs(pub Vec<(String, String)>);
+
+fn format_invalid(invalid: &[(String, String)]) -> String {
+    invalid
+        .iter()
+        .map(|(spec, err)| format!("'{}' ({})", spec, err))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Directory scanned by the `local` selection when none is configured. The
+/// crates found there are referred to by name, as `Crate::Local`, and looked
+/// up in the configured directory, if any, when the experiment runs.
+pub(crate) const DEFAULT_LOCAL_CRATES_DIR: &str = "local-crates";
 
 pub fn get_crates(list: &str) -> Fallible<Vec<Crate>> {
     // Placeholder implementation
@@ -18,6 +43,297 @@ pub fn get_crates(list: &str) -> Fallible<Vec<Crate>> {
     }
 }
 
+/// Resolve a crate selection into the concrete list of crates to test.
+///
+/// The selections drawn from the crates.io index need its location in
+/// `config`. They always pick the latest version of each crate.
+pub fn resolve(select: &CrateSelect, config: Option<&Config>) -> Fallible<Vec<Crate>> {
+    let index_config = || {
+        config.and_then(|c| c.index.as_ref()).ok_or_else(|| {
+            anyhow::anyhow!(
+                "crate selection '{}' requires the [index] section in the configuration",
+                select
+            )
+        })
+    };
+
+    match select {
+        CrateSelect::Demo => match config {
+            Some(config) => configured_demo_crates(config),
+            None => Ok(demo_crates()),
+        },
+        CrateSelect::Dummy => Ok(dummy_crates()),
+        CrateSelect::List(specs) => parse_specs(specs.iter().map(|spec| spec.as_str())),
+        CrateSelect::File(path) => {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("failed to read crate list {}", path.display()))?;
+            parse_specs(
+                content
+                    .lines()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty() && !line.starts_with('#')),
+            )
+        }
+        CrateSelect::Full => Ok(index_crates(index_config()?)?
+            .into_iter()
+            .map(Crate::Registry)
+            .collect()),
+        CrateSelect::Top(count) => top_crates(index_config()?, *count as usize),
+        CrateSelect::Random { count, seed } => {
+            let seed = seed.ok_or_else(|| {
+                anyhow::anyhow!("random crate selection requires a seed")
+            })?;
+            random_crates(index_config()?, *count as usize, seed)
+        }
+        CrateSelect::Local => local_crates(config.and_then(|c| c.local_crates_dir.as_deref())),
+        CrateSelect::ReverseDeps { crates, depth } => {
+            reverse_deps(index_config()?, crates, *depth)
+        }
+        // Resolved by `Database::resolve_crates`, which can read the results.
+        CrateSelect::FromExperiment { .. } => {
+            anyhow::bail!("crate selection '{}' requires the experiments database", select)
+        }
+    }
+}
+
+/// Parse every crate spec, reporting all the invalid ones at once.
+fn parse_specs<'a>(specs: impl Iterator<Item = &'a str>) -> Fallible<Vec<Crate>> {
+    let mut crates = Vec::new();
+    let mut invalid = Vec::new();
+    for spec in specs {
+        match spec.parse::<Crate>() {
+            Ok(krate) => crates.push(krate),
+            Err(err) => invalid.push((spec.to_string(), err.to_string())),
+        }
+    }
+    if !invalid.is_empty() {
+        invalid.sort();
+        return Err(InvalidCrateSpecs(invalid).into());
+    }
+
+    // Keep the order stable across runs of the same selection.
+    crates.sort();
+    crates.dedup();
+    Ok(crates)
+}
+
+/// The crates listed in the `[demo-crates]` section of the configuration.
+fn configured_demo_crates(config: &Config) -> Fallible<Vec<Crate>> {
+    let demo = &config.demo_crates;
+    let mut crates = Vec::new();
+
+    if !demo.crates.is_empty() {
+        let index = config.index.as_ref().map(|index| Index::new(&index.path));
+        let mut missing = Vec::new();
+        for entry in &demo.crates {
+            // Crates pinned as `name-version` don't need the index.
+            if let Ok(krate @ Crate::Registry(_)) = format!("reg:{}", entry).parse::<Crate>() {
+                crates.push(krate);
+                continue;
+            }
+            let latest = match &index {
+                Some(index) => index.krate(entry)?.and_then(|krate| {
+                    krate
+                        .latest_version()
+                        .map(|version| RegistryCrate::new(&krate.name, version))
+                }),
+                None => None,
+            };
+            match latest {
+                Some(krate) => crates.push(Crate::Registry(krate)),
+                None => missing.push(entry.as_str()),
+            }
+        }
+        if !missing.is_empty() {
+            match index {
+                Some(_) => anyhow::bail!("demo crates not found in the index: {}", missing.join(", ")),
+                None => anyhow::bail!(
+                    "demo crates without a version require the [index] section in the \
+                     configuration: {}",
+                    missing.join(", ")
+                ),
+            }
+        }
+    }
+
+    for repo in &demo.github_repos {
+        let (org, name) = parse_demo_repo("GitHub", repo)?;
+        crates.push(Crate::GitHub(GitHubRepo::new(org, name)));
+    }
+    for repo in &demo.gitee_repos {
+        let (org, name) = parse_demo_repo("Gitee", repo)?;
+        crates.push(Crate::Gitee(GiteeRepo::new(org, name)));
+    }
+
+    crates.extend(demo.local_crates.iter().cloned().map(Crate::Local));
+    Ok(crates)
+}
+
+fn parse_demo_repo<'a>(host: &str, repo: &'a str) -> Fallible<(&'a str, &'a str)> {
+    match repo.split_once('/') {
+        Some((org, name)) if !org.is_empty() && !name.is_empty() => Ok((org, name)),
+        _ => anyhow::bail!("invalid demo {} repository '{}', expected org/repo", host, repo),
+    }
+}
+
+/// Every crate in the local crates directory, sorted by directory name.
+///
+/// Crates in the default directory are referred to by name, so that the
+/// experiment doesn't depend on where crater runs from; crates anywhere else
+/// are referred to by their absolute path.
+fn local_crates(dir: Option<&Path>) -> Fallible<Vec<Crate>> {
+    let (dir, by_name) = match dir {
+        Some(dir) => (dir.to_path_buf(), false),
+        None => (PathBuf::from(DEFAULT_LOCAL_CRATES_DIR), true),
+    };
+    let entries = fs::read_dir(&dir)
+        .with_context(|| format!("failed to read local crates from {}", dir.display()))?;
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.join("Cargo.toml").is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| {
+            if by_name {
+                let name = path.file_name().unwrap_or_default();
+                Ok(Crate::Local(name.to_string_lossy().into_owned()))
+            } else {
+                Ok(Crate::Path(
+                    path.canonicalize()?.to_string_lossy().into_owned(),
+                ))
+            }
+        })
+        .collect()
+}
+
+/// The latest version of every crate in the index, sorted by name.
+fn index_crates(config: &IndexConfig) -> Fallible<Vec<RegistryCrate>> {
+    Ok(Index::new(&config.path)
+        .crates()?
+        .iter()
+        .filter_map(|krate| {
+            krate
+                .latest_version()
+                .map(|version| RegistryCrate::new(&krate.name, version))
+        })
+        .collect())
+}
+
+/// The `count` most downloaded crates, most downloaded first.
+fn top_crates(config: &IndexConfig, count: usize) -> Fallible<Vec<Crate>> {
+    let path = config.downloads.as_ref().ok_or_else(|| {
+        anyhow::anyhow!("top crate selection requires `index.downloads` in the configuration")
+    })?;
+    let downloads = index::load_downloads(path)?;
+
+    let mut crates = index_crates(config)?;
+    // Sorting is stable, so crates with the same count stay sorted by name.
+    crates.sort_by_key(|krate| {
+        std::cmp::Reverse(downloads.get(krate.name.as_str()).copied().unwrap_or(0))
+    });
+    crates.truncate(count);
+    Ok(crates.into_iter().map(Crate::Registry).collect())
+}
+
+/// The latest version of every crate depending on one of `roots`, sorted by
+/// name: their direct dependents at depth 1, the dependents of those at depth
+/// 2 and so on, or every transitive dependent when `depth` is `None`. Only
+/// the dependencies of the latest version of each crate are considered.
+///
+/// Dev-dependencies are followed for a single step: they build into the tests
+/// of the dependent, but not into the crates depending on it.
+fn reverse_deps(
+    config: &IndexConfig,
+    roots: &HashSet<String>,
+    depth: Option<u32>,
+) -> Fallible<Vec<Crate>> {
+    let crates = Index::new(&config.path).crates()?;
+    let mut missing = roots
+        .iter()
+        .filter(|root| {
+            crates
+                .binary_search_by(|krate| krate.name.as_str().cmp(root))
+                .is_err()
+        })
+        .map(|root| root.as_str())
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        missing.sort_unstable();
+        anyhow::bail!("crates not found in the index: {}", missing.join(", "));
+    }
+
+    // The dependents of each crate, and whether the dependency also builds
+    // into their own dependents.
+    let mut dependents: HashMap<&str, HashMap<&str, bool>> = HashMap::new();
+    let mut latest = HashMap::new();
+    for krate in &crates {
+        let Some(version) = krate.latest() else {
+            continue;
+        };
+        latest.insert(krate.name.as_str(), version.num.as_str());
+        for dep in &version.deps {
+            let propagates = dependents
+                .entry(dep.crate_name())
+                .or_default()
+                .entry(krate.name.as_str())
+                .or_insert(false);
+            *propagates |= !dep.is_dev();
+        }
+    }
+
+    let mut selected = HashSet::new();
+    let mut visited = roots.iter().map(|root| root.as_str()).collect::<HashSet<_>>();
+    let mut frontier = visited.iter().copied().collect::<Vec<_>>();
+    let mut level = 0;
+    while !frontier.is_empty() && depth.is_none_or(|depth| level < depth) {
+        level += 1;
+        let mut next = Vec::new();
+        for name in frontier {
+            for (&dependent, &propagates) in dependents.get(name).into_iter().flatten() {
+                selected.insert(dependent);
+                if propagates && visited.insert(dependent) {
+                    next.push(dependent);
+                }
+            }
+        }
+        frontier = next;
+    }
+
+    let mut selected = selected
+        .into_iter()
+        .filter_map(|name| {
+            latest
+                .get(name)
+                .map(|version| Crate::Registry(RegistryCrate::new(name, version)))
+        })
+        .collect::<Vec<_>>();
+    selected.sort();
+    Ok(selected)
+}
+
+/// `count` crates picked at random, the same ones for the same seed and
+/// index. Each crate is ranked by the hash of its name salted with the seed,
+/// which unlike a PRNG doesn't depend on the order the index is read in.
+fn random_crates(config: &IndexConfig, count: usize, seed: u64) -> Fallible<Vec<Crate>> {
+    let mut crates = index_crates(config)?;
+    crates.sort_by_cached_key(|krate| {
+        let mut hasher = Sha256::new();
+        hasher.update(seed.to_le_bytes());
+        hasher.update(krate.name.as_bytes());
+        hasher.finalize()
+    });
+    crates.truncate(count);
+    crates.sort();
+    Ok(crates.into_iter().map(Crate::Registry).collect())
+}
+
 fn demo_crates() -> Vec<Crate> {
     use crate::crates::sources::registry::RegistryCrate;
     
@@ -52,6 +368,223 @@ mod tests {
         assert_eq!(crates.len(), 1);
     }
 
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve(&CrateSelect::Demo, None).unwrap(), demo_crates());
+
+        let specs = ["reg:log-0.4.0", "gh:rust-lang/rust"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let crates = resolve(&CrateSelect::List(specs), None).unwrap();
+        assert_eq!(
+            crates,
+            vec![
+                Crate::Registry(crate::crates::RegistryCrate::new("log", "0.4.0")),
+                Crate::GitHub(crate::crates::GitHubRepo::new("rust-lang", "rust")),
+            ]
+        );
+
+        let invalid = ["serde", "gh:libc", "reg:log-0.4.0"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let err = resolve(&CrateSelect::List(invalid), None).unwrap_err();
+        let invalid = &err.downcast_ref::<InvalidCrateSpecs>().unwrap().0;
+        let specs = invalid.iter().map(|(spec, _)| spec.as_str()).collect::<Vec<_>>();
+        assert_eq!(specs, vec!["gh:libc", "serde"]);
+        assert!(resolve(&CrateSelect::Full, None).is_err());
+    }
+
+    fn index_config(dir: &std::path::Path) -> Config {
+        use crate::crates::index::tests::add_crate;
+
+        let index = dir.join("index");
+        add_crate(&index, "serde", &[("1.0.0", false), ("1.0.1", false)]);
+        add_crate(&index, "libc", &[("0.2.0", false)]);
+        add_crate(&index, "nix", &[("0.27.0", false)]);
+        add_crate(&index, "yanked", &[("0.1.0", true)]);
+        std::fs::write(
+            dir.join("crates.csv"),
+            "id,name,downloads\n1,serde,300\n2,libc,500\n3,nix,10\n",
+        )
+        .unwrap();
+
+        let mut config: Config = toml::from_str(include_str!("../../config.toml")).unwrap();
+        config.index = Some(IndexConfig {
+            path: index,
+            downloads: Some(dir.join("crates.csv")),
+        });
+        config
+    }
+
+    #[test]
+    fn test_resolve_from_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = index_config(dir.path());
+        let reg = |name, version| Crate::Registry(RegistryCrate::new(name, version));
+
+        assert_eq!(
+            resolve(&CrateSelect::Full, Some(&config)).unwrap(),
+            vec![reg("libc", "0.2.0"), reg("nix", "0.27.0"), reg("serde", "1.0.1")]
+        );
+        assert_eq!(
+            resolve(&CrateSelect::Top(2), Some(&config)).unwrap(),
+            vec![reg("libc", "0.2.0"), reg("serde", "1.0.1")]
+        );
+
+        let random = |count, seed| {
+            resolve(
+                &CrateSelect::Random {
+                    count,
+                    seed: Some(seed),
+                },
+                Some(&config),
+            )
+            .unwrap()
+        };
+        assert_eq!(random(2, 7).len(), 2);
+        assert_eq!(random(2, 7), random(2, 7));
+        assert_eq!(random(10, 7).len(), 3);
+        assert!((0..20).any(|seed| random(1, seed) != random(1, 7)));
+
+        assert!(resolve(&CrateSelect::Random { count: 1, seed: None }, Some(&config)).is_err());
+    }
+
+    #[test]
+    fn test_resolve_reverse_deps() {
+        use crate::crates::index::tests::add_crate_with_deps;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = index_config(dir.path());
+        let index = &config.index.as_ref().unwrap().path;
+        add_crate_with_deps(index, "mio", &[("0.8.0", false, &[("libc", "normal")])]);
+        add_crate_with_deps(index, "tokio", &[("1.0.0", false, &[("mio", "normal")])]);
+        add_crate_with_deps(index, "tower", &[("0.4.0", false, &[("tokio", "build")])]);
+        add_crate_with_deps(index, "quickcheck", &[("1.0.0", false, &[("libc", "dev")])]);
+        add_crate_with_deps(index, "proptest", &[("1.0.0", false, &[("quickcheck", "normal")])]);
+        // Only the latest version counts.
+        add_crate_with_deps(
+            index,
+            "rand",
+            &[("0.7.0", false, &[("libc", "normal")]), ("0.8.0", false, &[])],
+        );
+
+        let select = |s: &str| {
+            let crates = resolve(&s.parse().unwrap(), Some(&config)).unwrap();
+            crates.iter().map(|c| c.to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            select("rdeps:libc"),
+            vec!["reg:mio-0.8.0", "reg:quickcheck-1.0.0"]
+        );
+        assert_eq!(
+            select("rdeps:libc@2"),
+            vec!["reg:mio-0.8.0", "reg:quickcheck-1.0.0", "reg:tokio-1.0.0"]
+        );
+        assert_eq!(
+            select("rdeps:libc@all"),
+            vec!["reg:mio-0.8.0", "reg:quickcheck-1.0.0", "reg:tokio-1.0.0", "reg:tower-0.4.0"]
+        );
+        assert_eq!(select("rdeps:mio,tokio"), vec!["reg:tokio-1.0.0", "reg:tower-0.4.0"]);
+
+        let err = resolve(&"rdeps:libc,missing".parse().unwrap(), Some(&config)).unwrap_err();
+        assert!(err.to_string().contains("missing"));
+        assert!(resolve(&"rdeps:libc".parse().unwrap(), None).is_err());
+    }
+
+    #[test]
+    fn test_resolve_demo_from_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = index_config(dir.path());
+        config.demo_crates.crates = vec!["serde".into(), "libc".into()];
+        config.demo_crates.github_repos = vec!["rust-lang/libc".into()];
+        config.demo_crates.gitee_repos = vec!["openharmony/rust_ylong".into()];
+        config.demo_crates.local_crates = vec!["my-crate".into()];
+
+        assert_eq!(
+            resolve(&CrateSelect::Demo, Some(&config)).unwrap(),
+            vec![
+                Crate::Registry(RegistryCrate::new("serde", "1.0.1")),
+                Crate::Registry(RegistryCrate::new("libc", "0.2.0")),
+                Crate::GitHub(GitHubRepo::new("rust-lang", "libc")),
+                Crate::Gitee(GiteeRepo::new("openharmony", "rust_ylong")),
+                Crate::Local("my-crate".into()),
+            ]
+        );
+
+        config.demo_crates.crates.push("missing".into());
+        let err = resolve(&CrateSelect::Demo, Some(&config)).unwrap_err();
+        assert!(err.to_string().contains("missing"));
+
+        config.demo_crates.crates.clear();
+        config.demo_crates.github_repos = vec!["libc".into()];
+        assert!(resolve(&CrateSelect::Demo, Some(&config)).is_err());
+    }
+
+    #[test]
+    fn test_resolve_demo_without_index() {
+        // The shipped configuration has demo crates but no index.
+        let mut config: Config = toml::from_str(include_str!("../../config.toml")).unwrap();
+        assert!(config.index.is_none());
+        assert_eq!(
+            resolve(&CrateSelect::Demo, Some(&config)).unwrap(),
+            vec![Crate::Registry(RegistryCrate::new("lazy_static", "1.4.0"))]
+        );
+
+        config.demo_crates.crates.push("serde".into());
+        let err = resolve(&CrateSelect::Demo, Some(&config)).unwrap_err();
+        assert!(err.to_string().contains("[index]"));
+        assert!(err.to_string().contains("serde"));
+    }
+
+    #[test]
+    fn test_resolve_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("crates.txt");
+        fs::write(
+            &path,
+            "# crates from the partner team\nreg:log-0.4.0\n\n  gc:openharmony/foo  \nreg:log-0.4.0\n",
+        )
+        .unwrap();
+        assert_eq!(
+            resolve(&CrateSelect::File(path.clone()), None).unwrap(),
+            vec![
+                Crate::Registry(RegistryCrate::new("log", "0.4.0")),
+                Crate::GitCode(crate::crates::GitCodeRepo::new("openharmony", "foo")),
+            ]
+        );
+
+        fs::write(&path, "reg:log-0.4.0\nlog\n").unwrap();
+        let err = resolve(&CrateSelect::File(path), None).unwrap_err();
+        assert!(err.to_string().contains("'log'"));
+        assert!(resolve(&CrateSelect::File(dir.path().join("missing")), None).is_err());
+    }
+
+    #[test]
+    fn test_resolve_local() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b-crate", "a-crate"] {
+            fs::create_dir_all(dir.path().join(name)).unwrap();
+            fs::write(dir.path().join(name).join("Cargo.toml"), "").unwrap();
+        }
+        fs::create_dir_all(dir.path().join("not-a-crate")).unwrap();
+
+        let mut config: Config = toml::from_str(include_str!("../../config.toml")).unwrap();
+        config.local_crates_dir = Some(dir.path().to_path_buf());
+        let root = dir.path().canonicalize().unwrap();
+        assert_eq!(
+            resolve(&CrateSelect::Local, Some(&config)).unwrap(),
+            vec![
+                Crate::Path(root.join("a-crate").to_string_lossy().into_owned()),
+                Crate::Path(root.join("b-crate").to_string_lossy().into_owned()),
+            ]
+        );
+
+        config.local_crates_dir = Some(dir.path().join("missing"));
+        assert!(resolve(&CrateSelect::Local, Some(&config)).is_err());
+    }
+
     #[test]
     fn test_get_unknown_crates() {
         assert!(get_crates("unknown").is_err());
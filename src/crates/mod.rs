@@ -1,3 +1,4 @@
+pub(crate) mod index;
 pub(crate) mod lists;
 pub(crate) mod sources;
 
@@ -6,14 +7,19 @@ use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::str::FromStr;
 
+use crate::crates::sources::{fmt_revision, parse_revision};
+
 pub use crate::crates::sources::github::GitHubRepo;
 pub use crate::crates::sources::gitcode::GitCodeRepo;
+pub use crate::crates::sources::gitee::GiteeRepo;
 pub use crate::crates::sources::registry::RegistryCrate;
 
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone)]
 pub struct GitRepo {
     pub url: String,
     pub sha: Option<String>,
+    /// Directory of the crate inside the repository, when it isn't the root.
+    pub subpath: Option<String>,
 }
 
 impl GitRepo {
@@ -21,6 +27,7 @@ impl GitRepo {
         Self {
             url: url.to_string(),
             sha: None,
+            subpath: None,
         }
     }
 
@@ -28,17 +35,20 @@ impl GitRepo {
         Self {
             url: url.to_string(),
             sha: Some(sha.to_string()),
+            subpath: None,
         }
     }
+
+    pub fn with_subpath(mut self, subpath: &str) -> Self {
+        self.subpath = Some(subpath.to_string());
+        self
+    }
 }
 
 impl fmt::Display for GitRepo {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.url)?;
-        if let Some(ref sha) = self.sha {
-            write!(f, "#{}", sha)?;
-        }
-        Ok(())
+        fmt_revision(f, self.sha.as_deref(), self.subpath.as_deref())
     }
 }
 
@@ -48,20 +58,74 @@ pub enum Crate {
     Registry(RegistryCrate),
     GitHub(GitHubRepo),
     GitCode(GitCodeRepo),
+    Gitee(GiteeRepo),
     Local(String),
     Path(String),
     Git(GitRepo),
 }
 
 impl Crate {
+    /// The spec of the crate, which `FromStr` parses back. Same as `Display`.
+    ///
+    /// ```text
+    /// reg:<name>-<version>
+    /// gh:<org>/<repo>[#[<sha>][:<subpath>]]
+    /// gc:<org>/<repo>[#[<sha>][:<subpath>]]
+    /// ge:<org>/<repo>[#[<sha>][:<subpath>]]
+    /// git:<url>[#[<sha>][:<subpath>]]
+    /// local:<name>
+    /// path:<path>
+    /// ```
+    ///
+    /// A subpath without a commit is written `#:<subpath>`.
     pub fn id(&self) -> String {
+        self.to_string()
+    }
+
+    /// URL of the git repository of the crate, for the git-based sources.
+    pub fn git_url(&self) -> Option<String> {
+        match self {
+            Crate::GitHub(repo) => Some(repo.url()),
+            Crate::GitCode(repo) => Some(repo.url()),
+            Crate::Gitee(repo) => Some(repo.url()),
+            Crate::Git(repo) => Some(repo.url.clone()),
+            Crate::Registry(_) | Crate::Local(_) | Crate::Path(_) => None,
+        }
+    }
+
+    /// The commit the crate is pinned to, if any.
+    pub fn sha(&self) -> Option<&str> {
+        match self {
+            Crate::GitHub(repo) => repo.sha.as_deref(),
+            Crate::GitCode(repo) => repo.sha.as_deref(),
+            Crate::Gitee(repo) => repo.sha.as_deref(),
+            Crate::Git(repo) => repo.sha.as_deref(),
+            Crate::Registry(_) | Crate::Local(_) | Crate::Path(_) => None,
+        }
+    }
+
+    /// The crate pinned to the commit `sha`. Crates outside of git
+    /// repositories are returned unchanged.
+    pub fn with_sha(&self, sha: &str) -> Crate {
+        let mut krate = self.clone();
+        match &mut krate {
+            Crate::GitHub(repo) => repo.sha = Some(sha.to_string()),
+            Crate::GitCode(repo) => repo.sha = Some(sha.to_string()),
+            Crate::Gitee(repo) => repo.sha = Some(sha.to_string()),
+            Crate::Git(repo) => repo.sha = Some(sha.to_string()),
+            Crate::Registry(_) | Crate::Local(_) | Crate::Path(_) => {}
+        }
+        krate
+    }
+
+    /// Directory of the crate inside its repository, when it isn't the root.
+    pub fn subpath(&self) -> Option<&str> {
         match self {
-            Crate::Registry(krate) => format!("reg:{}", krate),
-            Crate::GitHub(repo) => format!("gh:{}", repo.slug()),
-            Crate::GitCode(repo) => format!("gc:{}", repo.slug()),
-            Crate::Local(name) => format!("local:{}", name),
-            Crate::Path(path) => format!("path:{}", path),
-            Crate::Git(repo) => format!("git:{}", repo.url),
+            Crate::GitHub(repo) => repo.subpath.as_deref(),
+            Crate::GitCode(repo) => repo.subpath.as_deref(),
+            Crate::Gitee(repo) => repo.subpath.as_deref(),
+            Crate::Git(repo) => repo.subpath.as_deref(),
+            Crate::Registry(_) | Crate::Local(_) | Crate::Path(_) => None,
         }
     }
 }
@@ -69,44 +133,86 @@ impl Crate {
 impl fmt::Display for Crate {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Crate::Registry(krate) => write!(f, "{}", krate),
-            Crate::GitHub(repo) => write!(f, "{}", repo),
-            Crate::GitCode(repo) => write!(f, "{}", repo),
+            Crate::Registry(krate) => write!(f, "reg:{}", krate),
+            Crate::GitHub(repo) => write!(f, "gh:{}", repo),
+            Crate::GitCode(repo) => write!(f, "gc:{}", repo),
+            Crate::Gitee(repo) => write!(f, "ge:{}", repo),
             Crate::Local(name) => write!(f, "local:{}", name),
             Crate::Path(path) => write!(f, "path:{}", path),
-            Crate::Git(repo) => write!(f, "{}", repo),
+            Crate::Git(repo) => write!(f, "git:{}", repo),
         }
     }
 }
 
+/// Split `<name>-<version>` at the first hyphen followed by a valid version:
+/// crate names may contain hyphens but no dots, which every version has.
+fn parse_registry(spec: &str) -> Option<RegistryCrate> {
+    spec.match_indices('-')
+        .map(|(i, _)| (&spec[..i], &spec[i + 1..]))
+        .find(|(name, version)| !name.is_empty() && semver::Version::parse(version).is_ok())
+        .map(|(name, version)| RegistryCrate::new(name, version))
+}
+
+fn parse_slug(slug: &str) -> Option<(&str, &str)> {
+    slug.split_once('/')
+        .filter(|(org, name)| !org.is_empty() && !name.is_empty() && !name.contains('/'))
+}
+
 impl FromStr for Crate {
     type Err = Error;
 
     fn from_str(s: &str) -> Fallible<Self> {
         if let Some(stripped) = s.strip_prefix("reg:") {
-            if let Some((name, version)) = stripped.split_once('-') {
-                Ok(Crate::Registry(RegistryCrate::new(name, version)))
-            } else {
-                anyhow::bail!("invalid registry crate format: {}", s)
-            }
+            parse_registry(stripped)
+                .map(Crate::Registry)
+                .ok_or_else(|| anyhow::anyhow!("invalid registry crate format: {}", s))
         } else if let Some(stripped) = s.strip_prefix("gh:") {
-            if let Some((org, name)) = stripped.split_once('/') {
-                Ok(Crate::GitHub(GitHubRepo::new(org, name)))
+            let (slug, sha, subpath) = parse_revision(stripped)?;
+            if let Some((org, name)) = parse_slug(slug) {
+                Ok(Crate::GitHub(GitHubRepo {
+                    sha,
+                    subpath,
+                    ..GitHubRepo::new(org, name)
+                }))
             } else {
                 anyhow::bail!("invalid github repo format: {}", s)
             }
         } else if let Some(stripped) = s.strip_prefix("gc:") {
-            if let Some((org, name)) = stripped.split_once('/') {
-                Ok(Crate::GitCode(GitCodeRepo::new(org, name)))
+            let (slug, sha, subpath) = parse_revision(stripped)?;
+            if let Some((org, name)) = parse_slug(slug) {
+                Ok(Crate::GitCode(GitCodeRepo {
+                    sha,
+                    subpath,
+                    ..GitCodeRepo::new(org, name)
+                }))
             } else {
                 anyhow::bail!("invalid gitcode repo format: {}", s)
             }
+        } else if let Some(stripped) = s.strip_prefix("ge:") {
+            let (slug, sha, subpath) = parse_revision(stripped)?;
+            if let Some((org, name)) = parse_slug(slug) {
+                Ok(Crate::Gitee(GiteeRepo {
+                    sha,
+                    subpath,
+                    ..GiteeRepo::new(org, name)
+                }))
+            } else {
+                anyhow::bail!("invalid gitee repo format: {}", s)
+            }
         } else if let Some(stripped) = s.strip_prefix("local:") {
             Ok(Crate::Local(stripped.to_string()))
         } else if let Some(stripped) = s.strip_prefix("path:") {
             Ok(Crate::Path(stripped.to_string()))
         } else if let Some(stripped) = s.strip_prefix("git:") {
-            Ok(Crate::Git(GitRepo::new(stripped)))
+            let (url, sha, subpath) = parse_revision(stripped)?;
+            if url.is_empty() {
+                anyhow::bail!("invalid git repo format: {}", s);
+            }
+            Ok(Crate::Git(GitRepo {
+                sha,
+                subpath,
+                ..GitRepo::new(url)
+            }))
         } else {
             anyhow::bail!("unknown crate format: {}", s)
         }
@@ -135,13 +241,72 @@ mod tests {
     #[test]
     fn test_crate_display() {
         let krate = Crate::Registry(RegistryCrate::new("tokio", "1.0.0"));
-        assert_eq!(krate.to_string(), "tokio-1.0.0");
+        assert_eq!(krate.to_string(), "reg:tokio-1.0.0");
 
         let krate = Crate::GitHub(GitHubRepo::new("tokio-rs", "tokio"));
-        assert_eq!(krate.to_string(), "tokio-rs/tokio");
+        assert_eq!(krate.to_string(), "gh:tokio-rs/tokio");
 
         let krate = Crate::GitCode(GitCodeRepo::new("tokio-rs", "tokio"));
-        assert_eq!(krate.to_string(), "tokio-rs/tokio");
+        assert_eq!(krate.to_string(), "gc:tokio-rs/tokio");
+
+        let krate = Crate::Git(GitRepo::with_sha("https://example.com/a.git", "abc123"));
+        assert_eq!(krate.to_string(), "git:https://example.com/a.git#abc123");
+    }
+
+    #[test]
+    fn test_crate_round_trip() {
+        for spec in [
+            "reg:serde-1.0.0",
+            "reg:serde-json-1.0.100",
+            "reg:sha-1-0.10.0",
+            "reg:tokio-1.0.0-alpha.1",
+            "gh:rust-lang/libc",
+            "gh:rust-lang/libc#0123abc",
+            "gh:rust-lang/cargo#0123abc:crates/cargo-util",
+            "gh:rust-lang/cargo#:crates/cargo-util",
+            "gc:openharmony/rust-lib#beef",
+            "ge:openharmony/rust-lib#beef:crates/ylong",
+            "git:https://example.com/repo.git",
+            "git:https://example.com/repo.git#abc123:sub/dir",
+            "local:my-crate",
+            "path:/path/to/crate",
+        ] {
+            let krate: Crate = spec.parse().unwrap();
+            assert_eq!(krate.to_string(), spec);
+            assert_eq!(krate.id(), spec);
+            assert_eq!(krate.to_string().parse::<Crate>().unwrap(), krate);
+        }
+
+        let krate: Crate = "reg:serde-json-1.0.100".parse().unwrap();
+        assert_eq!(
+            krate,
+            Crate::Registry(RegistryCrate::new("serde-json", "1.0.100"))
+        );
+        let krate: Crate = "gh:rust-lang/cargo#0123abc:crates/cargo-util".parse().unwrap();
+        assert_eq!(krate.subpath(), Some("crates/cargo-util"));
+        assert_eq!(
+            krate,
+            Crate::GitHub(
+                GitHubRepo::with_sha("rust-lang", "cargo", "0123abc")
+                    .with_subpath("crates/cargo-util")
+            )
+        );
+
+        for invalid in [
+            "reg:serde",
+            "reg:serde-json",
+            "reg:-1.0.0",
+            "gh:rust-lang",
+            "gh:rust-lang/cargo/extra",
+            "gh:rust-lang/libc#",
+            "gh:rust-lang/libc#main",
+            "gh:rust-lang/libc#abc:",
+            "gh:rust-lang/libc#abc:../escape",
+            "gh:rust-lang/libc#abc:/absolute",
+            "git:",
+        ] {
+            assert!(invalid.parse::<Crate>().is_err(), "{}", invalid);
+        }
     }
 
     #[test]
@@ -155,6 +320,9 @@ mod tests {
         let krate: Crate = "gc:rust-lang/rust".parse().unwrap();
         assert!(matches!(krate, Crate::GitCode(_)));
 
+        let krate: Crate = "ge:openharmony/rust".parse().unwrap();
+        assert_eq!(krate, Crate::Gitee(GiteeRepo::new("openharmony", "rust")));
+
         let krate: Crate = "local:my-crate".parse().unwrap();
         assert!(matches!(krate, Crate::Local(_)));
 
@@ -165,6 +333,20 @@ mod tests {
         assert!(matches!(krate, Crate::Git(_)));
     }
 
+    #[test]
+    fn test_crate_sha() {
+        let krate: Crate = "gh:rust-lang/cargo#:crates/xtask".parse().unwrap();
+        assert_eq!(krate.git_url().as_deref(), Some("https://github.com/rust-lang/cargo"));
+        assert_eq!(krate.sha(), None);
+        let pinned = krate.with_sha("abc123");
+        assert_eq!(pinned.sha(), Some("abc123"));
+        assert_eq!(pinned.to_string(), "gh:rust-lang/cargo#abc123:crates/xtask");
+
+        let krate: Crate = "reg:serde-1.0.0".parse().unwrap();
+        assert_eq!(krate.git_url(), None);
+        assert_eq!(krate.with_sha("abc123"), krate);
+    }
+
     #[test]
     fn test_git_repo() {
         let repo = GitRepo::new("https://github.com/rust-lang/rust");
@@ -1,3 +1,4 @@
+use crate::crates::sources::fmt_revision;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone)]
@@ -5,6 +6,8 @@ pub struct GitCodeRepo {
     pub org: String,
     pub name: String,
     pub sha: Option<String>,
+    /// Directory of the crate inside the repository, when it isn't the root.
+    pub subpath: Option<String>,
 }
 
 impl GitCodeRepo {
@@ -12,11 +15,16 @@ impl GitCodeRepo {
         format!("{}/{}", self.org, self.name)
     }
 
+    pub fn url(&self) -> String {
+        format!("https://gitcode.com/{}", self.slug())
+    }
+
     pub fn new(org: &str, name: &str) -> Self {
         Self {
             org: org.to_string(),
             name: name.to_string(),
             sha: None,
+            subpath: None,
         }
     }
 
@@ -25,17 +33,20 @@ impl GitCodeRepo {
             org: org.to_string(),
             name: name.to_string(),
             sha: Some(sha.to_string()),
+            subpath: None,
         }
     }
+
+    pub fn with_subpath(mut self, subpath: &str) -> Self {
+        self.subpath = Some(subpath.to_string());
+        self
+    }
 }
 
 impl std::fmt::Display for GitCodeRepo {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}", self.slug())?;
-        if let Some(ref sha) = self.sha {
-            write!(f, "#{}", sha)?;
-        }
-        Ok(())
+        fmt_revision(f, self.sha.as_deref(), self.subpath.as_deref())
     }
 }
 
@@ -47,6 +58,7 @@ mod tests {
     fn test_gitcode_repo_slug() {
         let repo = GitCodeRepo::new("rust-lang", "rust");
         assert_eq!(repo.slug(), "rust-lang/rust");
+        assert_eq!(repo.url(), "https://gitcode.com/rust-lang/rust");
     }
 
     #[test]
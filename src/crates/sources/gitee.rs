@@ -0,0 +1,82 @@
+use crate::crates::sources::fmt_revision;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone)]
+pub struct GiteeRepo {
+    pub org: String,
+    pub name: String,
+    pub sha: Option<String>,
+    /// Directory of the crate inside the repository, when it isn't the root.
+    pub subpath: Option<String>,
+}
+
+impl GiteeRepo {
+    pub fn slug(&self) -> String {
+        format!("{}/{}", self.org, self.name)
+    }
+
+    pub fn url(&self) -> String {
+        format!("https://gitee.com/{}", self.slug())
+    }
+
+    pub fn new(org: &str, name: &str) -> Self {
+        Self {
+            org: org.to_string(),
+            name: name.to_string(),
+            sha: None,
+            subpath: None,
+        }
+    }
+
+    pub fn with_sha(org: &str, name: &str, sha: &str) -> Self {
+        Self {
+            org: org.to_string(),
+            name: name.to_string(),
+            sha: Some(sha.to_string()),
+            subpath: None,
+        }
+    }
+
+    pub fn with_subpath(mut self, subpath: &str) -> Self {
+        self.subpath = Some(subpath.to_string());
+        self
+    }
+}
+
+impl std::fmt::Display for GiteeRepo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.slug())?;
+        fmt_revision(f, self.sha.as_deref(), self.subpath.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gitee_repo_slug() {
+        let repo = GiteeRepo::new("openharmony", "rust");
+        assert_eq!(repo.slug(), "openharmony/rust");
+        assert_eq!(repo.url(), "https://gitee.com/openharmony/rust");
+    }
+
+    #[test]
+    fn test_gitee_repo_display() {
+        let repo = GiteeRepo::new("tokio-rs", "tokio");
+        assert_eq!(repo.to_string(), "tokio-rs/tokio");
+
+        let repo = GiteeRepo::with_sha("serde-rs", "serde", "abc123");
+        assert_eq!(repo.to_string(), "serde-rs/serde#abc123");
+    }
+
+    #[test]
+    fn test_gitee_repo_serialization() {
+        let repo = GiteeRepo::with_sha("rust-lang", "crater", "def456");
+        let json = serde_json::to_string(&repo).unwrap();
+        let parsed: GiteeRepo = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.org, "rust-lang");
+        assert_eq!(parsed.name, "crater");
+        assert_eq!(parsed.sha, Some("def456".to_string()));
+    }
+}
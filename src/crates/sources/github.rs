@@ -1,3 +1,4 @@
+use crate::crates::sources::fmt_revision;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone)]
@@ -5,6 +6,8 @@ pub struct GitHubRepo {
     pub org: String,
     pub name: String,
     pub sha: Option<String>,
+    /// Directory of the crate inside the repository, when it isn't the root.
+    pub subpath: Option<String>,
 }
 
 impl GitHubRepo {
@@ -12,11 +15,16 @@ impl GitHubRepo {
         format!("{}/{}", self.org, self.name)
     }
 
+    pub fn url(&self) -> String {
+        format!("https://github.com/{}", self.slug())
+    }
+
     pub fn new(org: &str, name: &str) -> Self {
         Self {
             org: org.to_string(),
             name: name.to_string(),
             sha: None,
+            subpath: None,
         }
     }
 
@@ -25,17 +33,20 @@ impl GitHubRepo {
             org: org.to_string(),
             name: name.to_string(),
             sha: Some(sha.to_string()),
+            subpath: None,
         }
     }
+
+    pub fn with_subpath(mut self, subpath: &str) -> Self {
+        self.subpath = Some(subpath.to_string());
+        self
+    }
 }
 
 impl std::fmt::Display for GitHubRepo {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}", self.slug())?;
-        if let Some(ref sha) = self.sha {
-            write!(f, "#{}", sha)?;
-        }
-        Ok(())
+        fmt_revision(f, self.sha.as_deref(), self.subpath.as_deref())
     }
 }
 
@@ -47,6 +58,7 @@ mod tests {
     fn test_github_repo_slug() {
         let repo = GitHubRepo::new("rust-lang", "rust");
         assert_eq!(repo.slug(), "rust-lang/rust");
+        assert_eq!(repo.url(), "https://github.com/rust-lang/rust");
     }
 
     #[test]
@@ -56,6 +68,12 @@ mod tests {
 
         let repo = GitHubRepo::with_sha("serde-rs", "serde", "abc123");
         assert_eq!(repo.to_string(), "serde-rs/serde#abc123");
+
+        let repo = GitHubRepo::with_sha("rust-lang", "cargo", "abc123").with_subpath("crates/xtask");
+        assert_eq!(repo.to_string(), "rust-lang/cargo#abc123:crates/xtask");
+
+        let repo = GitHubRepo::new("rust-lang", "cargo").with_subpath("crates/xtask");
+        assert_eq!(repo.to_string(), "rust-lang/cargo#:crates/xtask");
     }
 
     #[test]
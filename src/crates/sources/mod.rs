@@ -1,3 +1,74 @@
 pub mod github;
 pub mod gitcode;
+pub mod gitee;
 pub mod registry;
+
+use crate::prelude::*;
+use std::fmt;
+use std::path::{Component, Path};
+use std::process::Command;
+
+/// Write the `#<sha>:<subpath>` suffix of a repository pinned to a commit, or
+/// whose crate lives in a subdirectory. Without a commit it's `#:<subpath>`.
+pub(crate) fn fmt_revision(
+    f: &mut fmt::Formatter,
+    sha: Option<&str>,
+    subpath: Option<&str>,
+) -> fmt::Result {
+    if sha.is_none() && subpath.is_none() {
+        return Ok(());
+    }
+    write!(f, "#{}", sha.unwrap_or(""))?;
+    if let Some(subpath) = subpath {
+        write!(f, ":{}", subpath)?;
+    }
+    Ok(())
+}
+
+/// The commit the `HEAD` of the git repository at `url` points to.
+pub(crate) fn remote_head(url: &str) -> Fallible<String> {
+    let output = Command::new("git")
+        .args(["ls-remote", url, "HEAD"])
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .context("failed to run git ls-remote")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git ls-remote {} failed: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .map(|sha| sha.to_string())
+        .ok_or_else(|| anyhow::anyhow!("no HEAD in git repository {}", url))
+}
+
+/// Split a repository spec from its `#<sha>:<subpath>` suffix, if any.
+pub(crate) fn parse_revision(spec: &str) -> Fallible<(&str, Option<String>, Option<String>)> {
+    let Some((repo, revision)) = spec.split_once('#') else {
+        return Ok((spec, None, None));
+    };
+    let (sha, subpath) = match revision.split_once(':') {
+        Some((sha, subpath)) => (sha, Some(subpath)),
+        None => (revision, None),
+    };
+
+    let sha = match sha {
+        "" if subpath.is_none() => anyhow::bail!("missing commit after '#': {}", spec),
+        "" => None,
+        sha if sha.chars().all(|c| c.is_ascii_hexdigit()) => Some(sha.to_string()),
+        sha => anyhow::bail!("invalid commit '{}': {}", sha, spec),
+    };
+    if let Some(subpath) = subpath {
+        let relative = Path::new(subpath)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        if subpath.is_empty() || !relative {
+            anyhow::bail!("invalid subpath '{}': {}", subpath, spec);
+        }
+    }
+    Ok((repo, sha, subpath.map(|subpath| subpath.to_string())))
+}
@@ -0,0 +1,488 @@
+use crate::actions::experiments::ExperimentActions;
+use crate::crates::{lists, sources, Crate};
+use crate::db::{Database, QueryUtils, Transaction};
+use crate::experiments::{CrateSelect, Experiment};
+use crate::prelude::*;
+use crate::report::{compare_results, CrateResult, RawTestResults, ReadResults, RunResult};
+use crate::results::{DatabaseDB, EncodedLog, ResolvedPatches, TestResult};
+use crate::runner::RecordProgress;
+use crate::toolchain::Toolchain;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+// Lifecycle of a crate in `experiment_crates.status`.
+const QUEUED: &str = "queued";
+const RUNNING: &str = "running";
+const COMPLETE: &str = "complete";
+
+/// The crate queue of an experiment, frozen when the experiment is defined
+/// and consumed by the runner through [`Database::next_crate`].
+impl Database {
+    /// Resolve `select` into the crates of a new experiment. Selections drawn
+    /// from the results of another experiment are resolved here, the others
+    /// by [`lists::resolve`].
+    pub(crate) fn resolve_crates(&self, select: &CrateSelect) -> Fallible<Vec<Crate>> {
+        match select {
+            CrateSelect::FromExperiment {
+                experiment,
+                categories,
+            } => {
+                let ex = self
+                    .get(experiment)?
+                    .ok_or_else(|| anyhow::anyhow!("experiment '{}' not found", experiment))?;
+                Ok(self
+                    .load_all_results(&ex)?
+                    .crates
+                    .into_iter()
+                    .filter(|result| categories.contains(&result.res))
+                    .map(|result| result.krate)
+                    .collect())
+            }
+            _ => lists::resolve(select, self.config()),
+        }
+    }
+
+    /// Pin every git crate of `experiment` that isn't pinned yet to the
+    /// current `HEAD` of its repository, so that both toolchains build the
+    /// same commit even if the repository changes during the run.
+    ///
+    /// The commit of each repository is resolved once and recorded in the
+    /// `shas` table. This runs `git ls-remote`, so it blocks; it fails if a
+    /// repository can't be reached, after pinning all the others.
+    pub fn pin_experiment_shas(&self, experiment: &str) -> Fallible<()> {
+        let conn = self.conn()?;
+        let recorded: Vec<(String, String)> = conn.query(
+            "SELECT repo, sha FROM shas WHERE experiment = ?",
+            [experiment],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let mut shas = recorded.into_iter().collect::<HashMap<_, _>>();
+        let mut unreachable = Vec::new();
+
+        for krate in self.experiment_crates(experiment)? {
+            let Some(url) = krate.git_url() else {
+                continue;
+            };
+            if krate.sha().is_some() {
+                continue;
+            }
+
+            let sha = match shas.get(&url) {
+                Some(sha) => sha.clone(),
+                None => match sources::remote_head(&url) {
+                    Ok(sha) => {
+                        conn.execute_query(
+                            "INSERT INTO shas (experiment, repo, sha) VALUES (?, ?, ?)",
+                            [experiment, &url, &sha],
+                        )?;
+                        shas.insert(url, sha.clone());
+                        sha
+                    }
+                    Err(err) => {
+                        unreachable.push(format!("{} ({:#})", url, err));
+                        continue;
+                    }
+                },
+            };
+            // The same crate might already be listed pinned to that commit.
+            conn.execute_query(
+                "UPDATE OR REPLACE experiment_crates SET crate = ? \
+                 WHERE experiment = ? AND crate = ?",
+                [&krate.with_sha(&sha).id(), experiment, &krate.id()],
+            )?;
+        }
+
+        if !unreachable.is_empty() {
+            unreachable.dedup();
+            anyhow::bail!("failed to pin the commit of {}", unreachable.join(", "));
+        }
+        Ok(())
+    }
+
+    /// Replace the crates tested by `experiment`.
+    pub fn set_experiment_crates(&self, experiment: &str, crates: &[Crate]) -> Fallible<()> {
+        let conn = self.conn()?;
+        let t = Transaction::new(&conn)?;
+        Self::replace_experiment_crates(&t, experiment, crates)?;
+        t.commit()
+    }
+
+    /// Replace the crates tested by `experiment` as part of the transaction `t`.
+    pub(crate) fn replace_experiment_crates(
+        t: &Transaction,
+        experiment: &str,
+        crates: &[Crate],
+    ) -> Fallible<()> {
+        t.execute_query(
+            "DELETE FROM experiment_crates WHERE experiment = ?",
+            [experiment],
+        )?;
+        for krate in crates {
+            t.execute_query(
+                "INSERT OR IGNORE INTO experiment_crates (experiment, crate, skipped, status) \
+                 VALUES (?, ?, 0, ?)",
+                [experiment, &krate.id(), QUEUED],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The crates tested by `experiment`, in the order they are run.
+    pub fn experiment_crates(&self, experiment: &str) -> Fallible<Vec<Crate>> {
+        let conn = self.conn()?;
+        let ids: Vec<String> = conn.query(
+            "SELECT crate FROM experiment_crates WHERE experiment = ? ORDER BY rowid",
+            [experiment],
+            |row| row.get(0),
+        )?;
+        ids.iter().map(|id| Crate::from_str(id)).collect()
+    }
+
+    /// Claim the next queued crate of `experiment`, or return `None` once
+    /// every crate was handed out. Safe to call from multiple workers.
+    pub fn next_crate(&self, experiment: &str) -> Fallible<Option<Crate>> {
+        let conn = self.conn()?;
+        let id: Option<String> = conn.get_row(
+            "UPDATE experiment_crates SET status = ? WHERE rowid = (
+                 SELECT rowid FROM experiment_crates
+                 WHERE experiment = ? AND status = ? AND skipped = 0
+                 ORDER BY rowid LIMIT 1
+             ) RETURNING crate",
+            [RUNNING, experiment, QUEUED],
+            |row| row.get(0),
+        )?;
+        id.map(|id| Crate::from_str(&id)).transpose()
+    }
+
+    /// Put back in the queue the crates whose run was interrupted, so that
+    /// resuming the experiment tests them again.
+    pub fn requeue_interrupted_crates(&self, experiment: &str) -> Fallible<usize> {
+        let conn = self.conn()?;
+        conn.execute_query(
+            "UPDATE experiment_crates SET status = ? WHERE experiment = ? AND status = ?",
+            [QUEUED, experiment, RUNNING],
+        )
+    }
+
+    fn complete_crate(&self, experiment: &str, krate: &Crate) -> Fallible<()> {
+        let conn = self.conn()?;
+        conn.execute_query(
+            "UPDATE experiment_crates SET status = ? WHERE experiment = ? AND crate = ?",
+            [COMPLETE, experiment, &krate.id()],
+        )?;
+        Ok(())
+    }
+}
+
+/// Git crates are listed with the commit they were pinned to, which shows in
+/// the report.
+impl ReadResults for Database {
+    fn load_all_results(&self, ex: &Experiment) -> Fallible<RawTestResults> {
+        let conn = self.conn()?;
+        let rows: Vec<(String, String, String)> = conn.query(
+            "SELECT crate, toolchain, result FROM results WHERE experiment = ?",
+            [&ex.name],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        let mut results = HashMap::new();
+        for (krate, toolchain, result) in rows {
+            let result: TestResult = serde_json::from_str(&result)?;
+            results.insert((krate, toolchain), result);
+        }
+
+        let mut crates = Vec::new();
+        for krate in self.experiment_crates(&ex.name)? {
+            let [start, end] = ex.toolchains.each_ref().map(|tc| {
+                results
+                    .get(&(krate.to_string(), tc.id()))
+                    .cloned()
+            });
+            let res = compare_results(ex.kind, &start, &end);
+            let runs = [start, end].map(|result| {
+                result.map(|result| RunResult {
+                    result,
+                    log: String::new(),
+                })
+            });
+            crates.push(CrateResult {
+                name: krate.to_string(),
+                url: crate_url(&krate),
+                krate,
+                status: None,
+                res,
+                runs,
+            });
+        }
+        Ok(RawTestResults { crates })
+    }
+}
+
+fn crate_url(krate: &Crate) -> String {
+    match krate {
+        Crate::Registry(krate) => {
+            format!("https://crates.io/crates/{}/{}", krate.name, krate.version)
+        }
+        other => other.git_url().unwrap_or_default(),
+    }
+}
+
+impl RecordProgress for Database {
+    fn record_progress(
+        &self,
+        ex: &Experiment,
+        krate: &Crate,
+        toolchain: &Toolchain,
+        log: &EncodedLog,
+        result: &TestResult,
+        patches: &ResolvedPatches,
+        _version: Option<(&Crate, &Crate)>,
+    ) -> Fallible<()> {
+        DatabaseDB::new(self.pool().clone()).store_result_with_patches(
+            &ex.name,
+            krate,
+            toolchain,
+            result,
+            Some(log),
+            patches,
+        )?;
+
+        // The crate is done once it has a result for every toolchain.
+        let conn = self.conn()?;
+        let recorded: Vec<String> = conn.query(
+            "SELECT toolchain FROM results WHERE experiment = ? AND crate = ?",
+            [&ex.name, &krate.to_string()],
+            |row| row.get(0),
+        )?;
+        let recorded = recorded.into_iter().collect::<HashSet<_>>();
+        if ex
+            .toolchains
+            .iter()
+            .all(|tc| recorded.contains(&tc.id()))
+        {
+            self.complete_crate(&ex.name, krate)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::experiments::{CreateExperiment, ExperimentActions};
+    use crate::crates::RegistryCrate;
+    use crate::experiments::{CrateSelect, ExperimentKind, Mode};
+    use crate::report::Comparison;
+
+    fn create(db: &Database) -> Experiment {
+        db.create(CreateExperiment {
+            name: "test-exp".to_string(),
+            kind: ExperimentKind::ToolchainComparison,
+            toolchains: ["stable".parse().unwrap(), "beta".parse().unwrap()],
+            mode: Mode::BuildAndTest,
+            crate_select: CrateSelect::Demo,
+            platform_issue: None,
+            callback_url: None,
+            priority: 0,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_next_crate() {
+        let db = Database::temp().unwrap();
+        create(&db);
+        let crates = db.experiment_crates("test-exp").unwrap();
+        assert_eq!(crates.len(), 3);
+
+        let mut claimed = Vec::new();
+        while let Some(krate) = db.next_crate("test-exp").unwrap() {
+            claimed.push(krate);
+        }
+        assert_eq!(claimed, crates);
+        assert!(db.next_crate("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_resume() {
+        let db = Database::temp().unwrap();
+        let ex = create(&db);
+        let log = EncodedLog::from_encoding(crate::results::EncodingType::Plain, Vec::new());
+
+        // The first crate is finished, the second one is interrupted after
+        // a single toolchain.
+        let first = db.next_crate("test-exp").unwrap().unwrap();
+        let second = db.next_crate("test-exp").unwrap().unwrap();
+        for tc in &ex.toolchains {
+            db.record_progress(
+                &ex,
+                &first,
+                tc,
+                &log,
+                &TestResult::TestPass,
+                &ResolvedPatches::new(),
+                None,
+            )
+            .unwrap();
+        }
+        db.record_progress(
+            &ex,
+            &second,
+            &ex.toolchains[0],
+            &log,
+            &TestResult::TestPass,
+            &ResolvedPatches::new(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(db.get_experiment_progress("test-exp").unwrap(), (1, 3));
+
+        assert_eq!(db.requeue_interrupted_crates("test-exp").unwrap(), 1);
+        let mut remaining = Vec::new();
+        while let Some(krate) = db.next_crate("test-exp").unwrap() {
+            remaining.push(krate);
+        }
+        assert_eq!(remaining.len(), 2);
+        assert_eq!(remaining[0], second);
+        assert!(!remaining.contains(&first));
+    }
+
+    #[test]
+    fn test_resolve_from_experiment() {
+        use crate::results::FailureReason;
+
+        let db = Database::temp().unwrap();
+        let ex = create(&db);
+        let log = EncodedLog::from_encoding(crate::results::EncodingType::Plain, Vec::new());
+        let crates = db.experiment_crates("test-exp").unwrap();
+        let fail = TestResult::BuildFail(FailureReason::Unknown);
+        let outcomes = [
+            (TestResult::TestPass, fail.clone()),
+            (fail.clone(), fail.clone()),
+            (TestResult::TestPass, TestResult::TestPass),
+        ];
+        for (krate, (start, end)) in crates.iter().zip(outcomes) {
+            for (tc, result) in ex.toolchains.iter().zip([start, end]) {
+                db.record_progress(&ex, krate, tc, &log, &result, &ResolvedPatches::new(), None)
+                    .unwrap();
+            }
+        }
+
+        let results = db.load_all_results(&ex).unwrap();
+        assert_eq!(
+            results.crates.iter().map(|r| r.res).collect::<Vec<_>>(),
+            vec![
+                Comparison::Regressed,
+                Comparison::SameBuildFail,
+                Comparison::SameTestPass
+            ]
+        );
+
+        let select = "ex:test-exp:regressed,build-fail".parse().unwrap();
+        assert_eq!(db.resolve_crates(&select).unwrap(), crates[..2].to_vec());
+        let select = "ex:test-exp:fixed".parse().unwrap();
+        assert!(db.resolve_crates(&select).unwrap().is_empty());
+        let select = "ex:missing:regressed".parse().unwrap();
+        assert!(db.resolve_crates(&select).is_err());
+    }
+
+    fn git(dir: &std::path::Path, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    #[test]
+    fn test_pin_experiment_shas() {
+        let dir = tempfile::tempdir().unwrap();
+        let bare = dir.path().join("repo.git");
+        let work = dir.path().join("work");
+        std::fs::create_dir_all(&work).unwrap();
+        git(dir.path(), &["init", "--quiet", "--bare", bare.to_str().unwrap()]);
+        git(&work, &["init", "--quiet"]);
+        git(&work, &["commit", "--quiet", "--allow-empty", "-m", "first"]);
+        git(&work, &["push", "--quiet", bare.to_str().unwrap(), "HEAD:refs/heads/master"]);
+        git(&bare, &["symbolic-ref", "HEAD", "refs/heads/master"]);
+        let first = git(&work, &["rev-parse", "HEAD"]);
+
+        let db = Database::temp().unwrap();
+        let url = bare.to_str().unwrap();
+        let pinned = format!("git:{}#0123abc", dir.path().join("other.git").display());
+        db.create(CreateExperiment {
+            name: "test-exp".to_string(),
+            kind: ExperimentKind::ToolchainComparison,
+            toolchains: ["stable".parse().unwrap(), "beta".parse().unwrap()],
+            mode: Mode::BuildAndTest,
+            crate_select: format!("list:git:{},git:{}#:sub,reg:log-0.4.0,{}", url, url, pinned)
+                .parse()
+                .unwrap(),
+            platform_issue: None,
+            callback_url: None,
+            priority: 0,
+        })
+        .unwrap();
+
+        db.run("test-exp").unwrap();
+        // A push during the run doesn't change the commit being tested.
+        git(&work, &["commit", "--quiet", "--allow-empty", "-m", "second"]);
+        git(&work, &["push", "--quiet", url, "HEAD:refs/heads/master"]);
+
+        let mut crates = db
+            .experiment_crates("test-exp")
+            .unwrap()
+            .iter()
+            .map(|krate| krate.to_string())
+            .collect::<Vec<_>>();
+        crates.sort();
+        let mut expected = vec![
+            format!("git:{}#{}", url, first),
+            format!("git:{}#{}:sub", url, first),
+            pinned,
+            "reg:log-0.4.0".to_string(),
+        ];
+        expected.sort();
+        assert_eq!(crates, expected);
+
+        let conn = db.conn().unwrap();
+        let shas: Vec<(String, String)> = conn
+            .query("SELECT repo, sha FROM shas WHERE experiment = ?", ["test-exp"], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(shas, vec![(url.to_string(), first)]);
+        drop(conn);
+
+        // An experiment can't start with a repository left unpinned.
+        let missing = dir.path().join("missing.git");
+        db.create(CreateExperiment {
+            name: "unreachable-exp".to_string(),
+            kind: ExperimentKind::ToolchainComparison,
+            toolchains: ["stable".parse().unwrap(), "beta".parse().unwrap()],
+            mode: Mode::BuildAndTest,
+            crate_select: format!("list:git:{}", missing.display()).parse().unwrap(),
+            platform_issue: None,
+            callback_url: None,
+            priority: 0,
+        })
+        .unwrap();
+        let err = db.run("unreachable-exp").unwrap_err();
+        assert!(err.to_string().contains("missing.git"));
+        let ex = db.get("unreachable-exp").unwrap().unwrap();
+        assert_eq!(ex.status, crate::experiments::Status::Queued);
+    }
+
+    #[test]
+    fn test_set_experiment_crates() {
+        let db = Database::temp().unwrap();
+        create(&db);
+
+        let krate = Crate::Registry(RegistryCrate::new("log", "0.4.0"));
+        db.set_experiment_crates("test-exp", &[krate.clone(), krate.clone()])
+            .unwrap();
+        assert_eq!(db.experiment_crates("test-exp").unwrap(), vec![krate]);
+    }
+}
@@ -1,19 +1,34 @@
+use crate::config::Config;
 use crate::db::DatabasePool;
-use crate::experiments::{Assignee, CapLints, Experiment, Mode, PlatformIssue, Status};
+use crate::experiments::{
+    Assignee, CapLints, CrateSelect, Experiment, ExperimentKind, Mode, PlatformIssue, Status,
+};
 use crate::prelude::*;
 use crate::toolchain::Toolchain;
 use chrono::{DateTime, Utc};
 use rusqlite::Row;
 use std::str::FromStr;
+use std::sync::Arc;
 
 /// Database wrapper for experiment operations
 pub struct Database {
     pool: DatabasePool,
+    config: Option<Arc<Config>>,
 }
 
 impl Database {
     pub fn new(pool: DatabasePool) -> Self {
-        Database { pool }
+        Database { pool, config: None }
+    }
+
+    /// Use `config` to resolve the crate selections of new experiments.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = Some(Arc::new(config));
+        self
+    }
+
+    pub(crate) fn config(&self) -> Option<&Config> {
+        self.config.as_deref()
     }
 
     /// Open the default database
@@ -29,6 +44,10 @@ impl Database {
         Ok(Database::new(pool))
     }
 
+    pub(super) fn pool(&self) -> &DatabasePool {
+        &self.pool
+    }
+
     /// Get a connection from the pool
     pub fn conn(&self) -> Fallible<r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>> {
         Ok(self.pool.get()?)
@@ -37,6 +56,7 @@ impl Database {
     /// Helper to parse experiment from database row
     pub(crate) fn parse_experiment(row: &Row) -> rusqlite::Result<Experiment> {
         let name: String = row.get("name")?;
+        let kind_str: String = row.get("kind")?;
         let mode_str: String = row.get("mode")?;
         let cap_lints_str: String = row.get("cap_lints")?;
         let toolchain_start: Option<String> = row.get("toolchain_start")?;
@@ -53,6 +73,11 @@ impl Database {
         let report_url: Option<String> = row.get("report_url")?;
         let ignore_blacklist: i32 = row.get("ignore_blacklist")?;
         let requirement: Option<String> = row.get("requirement")?;
+        let crate_select_str: Option<String> = row.get("crate_select")?;
+
+        let kind = ExperimentKind::from_str(&kind_str).map_err(|_| {
+            rusqlite::Error::InvalidQuery
+        })?;
 
         let mode = Mode::from_str(&mode_str).map_err(|_| {
             rusqlite::Error::InvalidQuery
@@ -130,6 +155,7 @@ impl Database {
 
         Ok(Experiment {
             name,
+            kind,
             toolchains,
             mode,
             cap_lints,
@@ -143,6 +169,8 @@ impl Database {
             report_url,
             ignore_blacklist: ignore_blacklist != 0,
             requirement,
+            // Selections stored before they could all be printed are lost.
+            crate_select: crate_select_str.and_then(|s| CrateSelect::from_str(&s).ok()),
         })
     }
 }
@@ -1,4 +1,6 @@
+use crate::db::QueryUtils;
 use crate::prelude::*;
+use crate::toolchain::Toolchain;
 use rusqlite::Connection;
 
 pub fn execute(conn: &Connection) -> Fallible<()> {
@@ -24,7 +26,10 @@ pub fn execute(conn: &Connection) -> Fallible<()> {
     for migration in MIGRATIONS {
         if !executed_migrations.contains(migration.name) {
             info!("executing migration: {}", migration.name);
-            conn.execute_batch(migration.sql)?;
+            match migration.kind {
+                MigrationKind::Sql(sql) => conn.execute_batch(sql)?,
+                MigrationKind::Code(code) => code(conn)?,
+            }
             conn.execute(
                 "INSERT INTO migrations (name, executed_at) VALUES (?, datetime('now'))",
                 [migration.name],
@@ -37,13 +42,19 @@ pub fn execute(conn: &Connection) -> Fallible<()> {
 
 struct Migration {
     name: &'static str,
-    sql: &'static str,
+    kind: MigrationKind,
+}
+
+enum MigrationKind {
+    Sql(&'static str),
+    /// Data migrations that can't be expressed in SQL.
+    Code(fn(&Connection) -> Fallible<()>),
 }
 
 const MIGRATIONS: &[Migration] = &[
     Migration {
         name: "create_experiments_table",
-        sql: "
+        kind: MigrationKind::Sql("
             CREATE TABLE experiments (
                 name TEXT PRIMARY KEY,
                 mode TEXT NOT NULL,
@@ -64,11 +75,11 @@ const MIGRATIONS: &[Migration] = &[
 
             CREATE INDEX experiments__status ON experiments (status);
             CREATE INDEX experiments__assigned_to ON experiments (assigned_to);
-        ",
+        "),
     },
     Migration {
         name: "create_results_table",
-        sql: "
+        kind: MigrationKind::Sql("
             CREATE TABLE results (
                 experiment TEXT NOT NULL,
                 crate TEXT NOT NULL,
@@ -80,11 +91,11 @@ const MIGRATIONS: &[Migration] = &[
             );
 
             CREATE INDEX results__experiment ON results (experiment);
-        ",
+        "),
     },
     Migration {
         name: "create_shas_table",
-        sql: "
+        kind: MigrationKind::Sql("
             CREATE TABLE shas (
                 experiment TEXT NOT NULL,
                 org TEXT NOT NULL,
@@ -93,11 +104,11 @@ const MIGRATIONS: &[Migration] = &[
                 PRIMARY KEY (experiment, org, name),
                 FOREIGN KEY (experiment) REFERENCES experiments(name) ON DELETE CASCADE
             );
-        ",
+        "),
     },
     Migration {
         name: "create_saved_names_table",
-        sql: "
+        kind: MigrationKind::Sql("
             CREATE TABLE saved_names (
                 experiment TEXT NOT NULL,
                 toolchain TEXT NOT NULL,
@@ -105,11 +116,11 @@ const MIGRATIONS: &[Migration] = &[
                 PRIMARY KEY (experiment, toolchain),
                 FOREIGN KEY (experiment) REFERENCES experiments(name) ON DELETE CASCADE
             );
-        ",
+        "),
     },
     Migration {
         name: "create_experiment_crates_table",
-        sql: "
+        kind: MigrationKind::Sql("
             CREATE TABLE experiment_crates (
                 experiment TEXT NOT NULL,
                 crate TEXT NOT NULL,
@@ -119,11 +130,11 @@ const MIGRATIONS: &[Migration] = &[
             );
 
             CREATE INDEX experiment_crates__experiment ON experiment_crates (experiment);
-        ",
+        "),
     },
     Migration {
         name: "create_experiment_metadata_table",
-        sql: "
+        kind: MigrationKind::Sql("
             CREATE TABLE IF NOT EXISTS experiment_metadata (
                 experiment TEXT PRIMARY KEY,
                 callback_url TEXT,
@@ -134,11 +145,11 @@ const MIGRATIONS: &[Migration] = &[
             );
 
             CREATE INDEX experiment_metadata__experiment ON experiment_metadata(experiment);
-        ",
+        "),
     },
     Migration {
         name: "rename_github_issue_to_platform_issue",
-        sql: "
+        kind: MigrationKind::Sql("
             -- Add new platform_issue columns for platform-agnostic design
             -- platform_issue: stores platform identifier (github, gitcode, gitlab, etc.)
             -- platform_issue_url: stores the HTML URL for the issue/PR
@@ -151,17 +162,17 @@ const MIGRATIONS: &[Migration] = &[
             -- Note: platform_issue_identifier is a new field, so no migration needed
             UPDATE experiments SET platform_issue = github_issue WHERE github_issue IS NOT NULL;
             UPDATE experiments SET platform_issue_url = github_issue_url WHERE github_issue_url IS NOT NULL;
-        ",
+        "),
     },
     Migration {
         name: "add_requirement_column",
-        sql: "
+        kind: MigrationKind::Sql("
             ALTER TABLE experiments ADD COLUMN requirement TEXT;
-        ",
+        "),
     },
     Migration {
         name: "create_agents_table",
-        sql: "
+        kind: MigrationKind::Sql("
             CREATE TABLE IF NOT EXISTS agents (
                 id TEXT PRIMARY KEY,
                 name TEXT NOT NULL,
@@ -174,11 +185,11 @@ const MIGRATIONS: &[Migration] = &[
             );
 
             CREATE INDEX IF NOT EXISTS idx_agents_status ON agents(status);
-        ",
+        "),
     },
     Migration {
         name: "create_api_tokens_table",
-        sql: "
+        kind: MigrationKind::Sql("
             CREATE TABLE IF NOT EXISTS api_tokens (
                 token TEXT PRIMARY KEY,
                 name TEXT NOT NULL,
@@ -188,6 +199,211 @@ const MIGRATIONS: &[Migration] = &[
             );
 
             CREATE INDEX IF NOT EXISTS idx_tokens_name ON api_tokens(name);
-        ",
+        "),
+    },
+    Migration {
+        name: "add_results_log_encoding",
+        kind: MigrationKind::Sql("
+            ALTER TABLE results ADD COLUMN encoding TEXT NOT NULL DEFAULT 'plain';
+        "),
+    },
+    Migration {
+        name: "add_experiments_kind",
+        kind: MigrationKind::Sql("
+            ALTER TABLE experiments ADD COLUMN kind TEXT NOT NULL DEFAULT 'toolchain-comparison';
+        "),
+    },
+    Migration {
+        name: "add_experiment_crates_status",
+        kind: MigrationKind::Sql("
+            ALTER TABLE experiment_crates ADD COLUMN status TEXT NOT NULL DEFAULT 'queued';
+        "),
+    },
+    Migration {
+        name: "add_experiments_crate_select",
+        kind: MigrationKind::Sql("
+            ALTER TABLE experiments ADD COLUMN crate_select TEXT;
+        "),
+    },
+    Migration {
+        name: "prefix_results_crate_specs",
+        kind: MigrationKind::Sql("
+            -- Results used to be keyed by the display name of the crate,
+            -- which dropped the source prefix: recover the full spec from
+            -- the crate list of the experiment when possible.
+            UPDATE results SET crate = experiment_crates.crate
+            FROM experiment_crates
+            WHERE experiment_crates.experiment = results.experiment
+              AND results.crate = CASE
+                  WHEN experiment_crates.crate LIKE 'reg:%'
+                    OR experiment_crates.crate LIKE 'git:%'
+                      THEN substr(experiment_crates.crate, 5)
+                  WHEN experiment_crates.crate LIKE 'gh:%'
+                    OR experiment_crates.crate LIKE 'gc:%'
+                    OR experiment_crates.crate LIKE 'ge:%'
+                      THEN substr(experiment_crates.crate, 4)
+                  ELSE experiment_crates.crate
+              END;
+
+            -- Otherwise guess it from the shape of the display name. An
+            -- `org/name` may come from any git host, so it's left as is.
+            UPDATE results SET crate = CASE
+                WHEN crate LIKE '%://%' THEN 'git:' || crate
+                ELSE 'reg:' || crate
+            END
+            WHERE (crate LIKE '%://%' OR crate NOT LIKE '%/%')
+              AND crate NOT LIKE 'reg:%' AND crate NOT LIKE 'gh:%'
+              AND crate NOT LIKE 'gc:%' AND crate NOT LIKE 'ge:%'
+              AND crate NOT LIKE 'git:%' AND crate NOT LIKE 'local:%'
+              AND crate NOT LIKE 'path:%';
+        "),
+    },
+    Migration {
+        name: "key_shas_by_repository",
+        kind: MigrationKind::Sql("
+            -- Nothing wrote the table before: key it by the URL of the
+            -- repository, which unlike org/name is unique across hosts.
+            DROP TABLE shas;
+            CREATE TABLE shas (
+                experiment TEXT NOT NULL,
+                repo TEXT NOT NULL,
+                sha TEXT NOT NULL,
+                PRIMARY KEY (experiment, repo),
+                FOREIGN KEY (experiment) REFERENCES experiments(name) ON DELETE CASCADE
+            );
+        "),
+    },
+    Migration {
+        name: "canonicalize_toolchains",
+        kind: MigrationKind::Code(canonicalize_toolchains),
+    },
+    Migration {
+        name: "add_results_patches",
+        kind: MigrationKind::Sql("
+            -- Commits the crates patched by the toolchain resolved to, as a
+            -- JSON object keyed by crate name.
+            ALTER TABLE results ADD COLUMN patches TEXT;
+        "),
     },
 ];
+
+/// Rewrite the toolchains stored by experiments and results with their
+/// canonical id, so that results map back to the toolchains of their
+/// experiment whatever order the spec listed its flags in.
+fn canonicalize_toolchains(conn: &Connection) -> Fallible<()> {
+    let canonical = |spec: String| match spec.parse::<Toolchain>() {
+        Ok(toolchain) => toolchain.id(),
+        Err(err) => {
+            warn!("keeping invalid toolchain {}: {:#}", spec, err);
+            spec
+        }
+    };
+
+    let experiments: Vec<(String, Option<String>, Option<String>)> = conn.query(
+        "SELECT name, toolchain_start, toolchain_end FROM experiments",
+        [] as [&str; 0],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    for (name, start, end) in experiments {
+        conn.execute(
+            "UPDATE experiments SET toolchain_start = ?, toolchain_end = ? WHERE name = ?",
+            rusqlite::params![start.map(canonical), end.map(canonical), name],
+        )?;
+    }
+
+    let toolchains: Vec<String> = conn.query(
+        "SELECT DISTINCT toolchain FROM results",
+        [] as [&str; 0],
+        |row| row.get(0),
+    )?;
+    for toolchain in toolchains {
+        let id = canonical(toolchain.clone());
+        if id != toolchain {
+            conn.execute(
+                "UPDATE OR REPLACE results SET toolchain = ? WHERE toolchain = ?",
+                [&id, &toolchain],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_memory_pool;
+
+    #[test]
+    fn test_prefix_results_crate_specs() {
+        let pool = create_memory_pool().unwrap();
+        let conn = pool.get().unwrap();
+        conn.execute_batch(
+            "INSERT INTO experiments (name, mode, cap_lints, priority, created_at, status)
+             VALUES ('ex', 'build-and-test', 'warn', 0, '2024-01-01T00:00:00Z', 'queued');
+             INSERT INTO experiment_crates (experiment, crate, skipped)
+             VALUES ('ex', 'gc:openharmony/foo', 0), ('ex', 'ge:openharmony/bar', 0),
+                    ('ex', 'reg:serde-json-1.0.0', 0);
+             INSERT INTO results (experiment, crate, toolchain, result) VALUES
+                 ('ex', 'openharmony/foo', 'stable', '\"test-pass\"'),
+                 ('ex', 'openharmony/bar', 'stable', '\"test-pass\"'),
+                 ('ex', 'serde-json-1.0.0', 'stable', '\"test-pass\"'),
+                 ('ex', 'rust-lang/libc', 'stable', '\"test-pass\"'),
+                 ('ex', 'log-0.4.0', 'stable', '\"test-pass\"'),
+                 ('ex', 'https://example.com/repo.git#abc', 'stable', '\"test-pass\"'),
+                 ('ex', 'local:foo', 'stable', '\"test-pass\"');
+             DELETE FROM migrations WHERE name = 'prefix_results_crate_specs';",
+        )
+        .unwrap();
+        execute(&conn).unwrap();
+
+        let mut crates: Vec<String> = conn
+            .query("SELECT crate FROM results", [] as [&str; 0], |row| row.get(0))
+            .unwrap();
+        crates.sort();
+        // The host of a repository missing from the crate list is unknown.
+        assert_eq!(
+            crates,
+            vec![
+                "gc:openharmony/foo",
+                "ge:openharmony/bar",
+                "git:https://example.com/repo.git#abc",
+                "local:foo",
+                "reg:log-0.4.0",
+                "reg:serde-json-1.0.0",
+                "rust-lang/libc",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_toolchains() {
+        let pool = create_memory_pool().unwrap();
+        let conn = pool.get().unwrap();
+        conn.execute_batch(
+            "INSERT INTO experiments
+                 (name, mode, cap_lints, priority, created_at, status, toolchain_start, toolchain_end)
+             VALUES ('ex', 'build-and-test', 'warn', 0, '2024-01-01T00:00:00Z', 'queued',
+                     'stable', 'stable+rustflags=-Copt-level=1+target=x86_64-unknown-linux-gnu');
+             INSERT INTO results (experiment, crate, toolchain, result) VALUES
+                 ('ex', 'reg:log-0.4.0', 'stable', '\"test-pass\"'),
+                 ('ex', 'reg:log-0.4.0',
+                  'stable+rustflags=-Copt-level=1+target=x86_64-unknown-linux-gnu',
+                  '\"test-pass\"');
+             DELETE FROM migrations WHERE name = 'canonicalize_toolchains';",
+        )
+        .unwrap();
+        execute(&conn).unwrap();
+
+        let end: Vec<String> = conn
+            .query("SELECT toolchain_end FROM experiments", [] as [&str; 0], |row| row.get(0))
+            .unwrap();
+        let canonical = "stable+target=x86_64-unknown-linux-gnu+rustflags=-Copt-level=1";
+        assert_eq!(end, vec![canonical]);
+
+        let mut toolchains: Vec<String> = conn
+            .query("SELECT toolchain FROM results", [] as [&str; 0], |row| row.get(0))
+            .unwrap();
+        toolchains.sort();
+        assert_eq!(toolchains, vec!["stable", canonical]);
+    }
+}
@@ -1,3 +1,4 @@
+mod crates;
 pub mod experiments;
 pub mod migrations;
 
@@ -190,6 +191,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::len_zero)]
     fn test_query_utils() {
         let pool = create_memory_pool().expect("failed to create memory pool");
         let conn = pool.get().expect("failed to get connection");
@@ -1,9 +1,11 @@
 use crate::prelude::*;
-use crate::toolchain::Toolchain;
+use crate::report::Comparison;
+use crate::toolchain::{is_ohos_target, Toolchain};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::fmt;
+use std::path::PathBuf;
 use std::str::FromStr;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -22,6 +24,7 @@ pub struct PlatformIssue {
 #[serde(rename_all = "kebab-case")]
 pub struct Experiment {
     pub name: String,
+    pub kind: ExperimentKind,
     pub toolchains: [Toolchain; 2],
     pub mode: Mode,
     pub cap_lints: CapLints,
@@ -35,6 +38,8 @@ pub struct Experiment {
     pub report_url: Option<String>,
     pub ignore_blacklist: bool,
     pub requirement: Option<String>,
+    /// The selection the crates of the experiment were resolved from.
+    pub crate_select: Option<CrateSelect>,
 }
 
 impl Experiment {
@@ -74,6 +79,55 @@ string_enum! {
 
 from_into_string!(Mode);
 
+string_enum! {
+    pub enum ExperimentKind {
+        ToolchainComparison => "toolchain-comparison",
+        CrossTarget => "cross-target",
+    }
+}
+
+from_into_string!(ExperimentKind);
+
+impl ExperimentKind {
+    /// Check that the toolchains make sense for this kind of experiment.
+    ///
+    /// The two toolchains must always differ. A cross-target experiment
+    /// compares a crate built for the host (or any other non-OHOS target) with
+    /// the same crate built for an OpenHarmony target, so its toolchains must
+    /// differ only in their target.
+    pub fn validate(&self, toolchains: &[Toolchain; 2]) -> Fallible<()> {
+        // Results are keyed by toolchain, the runs of identical ones would
+        // overwrite each other.
+        if toolchains[0].id() == toolchains[1].id() {
+            anyhow::bail!("the two toolchains of an experiment must differ");
+        }
+        match self {
+            ExperimentKind::ToolchainComparison => Ok(()),
+            ExperimentKind::CrossTarget => {
+                let [start, end] = toolchains;
+                let mut end_on_start_target = end.clone();
+                end_on_start_target.target = start.target.clone();
+                if end_on_start_target != *start {
+                    anyhow::bail!(
+                        "the toolchains of a cross-target experiment must differ only in their target"
+                    );
+                }
+                if start.target.as_deref().is_some_and(is_ohos_target) {
+                    anyhow::bail!(
+                        "the first toolchain of a cross-target experiment must not target OpenHarmony"
+                    );
+                }
+                if !end.target.as_deref().is_some_and(is_ohos_target) {
+                    anyhow::bail!(
+                        "the second toolchain of a cross-target experiment must target OpenHarmony"
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
 string_enum! {
     pub enum CapLints {
         Allow => "allow",
@@ -93,8 +147,36 @@ pub enum CrateSelect {
     Top(u32),
     Local,
     Dummy,
-    Random(u32),
+    Random { count: u32, seed: Option<u64> },
+    /// Crate specs, e.g. `reg:serde-1.0.0` or `gh:org/repo`.
     List(HashSet<String>),
+    /// File with one crate spec per line, read when the experiment is created.
+    File(PathBuf),
+    /// Crates of the index depending on one of `crates`, up to `depth` steps
+    /// away, or at any distance when `depth` is `None`.
+    ReverseDeps {
+        crates: HashSet<String>,
+        depth: Option<u32>,
+    },
+    /// Crates of another experiment whose results fall in one of `categories`.
+    FromExperiment {
+        experiment: String,
+        categories: HashSet<Comparison>,
+    },
+}
+
+impl CrateSelect {
+    /// Pick a seed for random selections that don't have one yet, so that
+    /// the experiment can later be re-created with the same crates.
+    pub fn with_seed(self) -> Self {
+        match self {
+            CrateSelect::Random { count, seed: None } => CrateSelect::Random {
+                count,
+                seed: Some(uuid::Uuid::new_v4().as_u64_pair().0),
+            },
+            other => other,
+        }
+    }
 }
 
 impl fmt::Display for CrateSelect {
@@ -105,8 +187,35 @@ impl fmt::Display for CrateSelect {
             CrateSelect::Top(count) => write!(f, "top-{}", count),
             CrateSelect::Local => write!(f, "local"),
             CrateSelect::Dummy => write!(f, "dummy"),
-            CrateSelect::Random(count) => write!(f, "random-{}", count),
-            CrateSelect::List(_) => write!(f, "list"),
+            CrateSelect::Random { count, seed: None } => write!(f, "random-{}", count),
+            CrateSelect::Random {
+                count,
+                seed: Some(seed),
+            } => write!(f, "random-{}@{}", count, seed),
+            CrateSelect::List(specs) => {
+                let mut specs = specs.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+                specs.sort_unstable();
+                write!(f, "list:{}", specs.join(","))
+            }
+            CrateSelect::File(path) => write!(f, "file:{}", path.display()),
+            CrateSelect::ReverseDeps { crates, depth } => {
+                let mut crates = crates.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+                crates.sort_unstable();
+                write!(f, "rdeps:{}", crates.join(","))?;
+                match depth {
+                    Some(1) => Ok(()),
+                    Some(depth) => write!(f, "@{}", depth),
+                    None => write!(f, "@all"),
+                }
+            }
+            CrateSelect::FromExperiment {
+                experiment,
+                categories,
+            } => {
+                let mut categories = categories.iter().map(|c| c.to_str()).collect::<Vec<_>>();
+                categories.sort_unstable();
+                write!(f, "ex:{}:{}", experiment, categories.join(","))
+            }
         }
     }
 }
@@ -125,10 +234,74 @@ impl FromStr for CrateSelect {
                     .map_err(|_| anyhow::anyhow!("invalid top count: {}", s))?;
                 Ok(CrateSelect::Top(count))
             }
+            s if s.starts_with("list:") => {
+                let specs = s[5..]
+                    .split(',')
+                    .map(|spec| spec.trim())
+                    .filter(|spec| !spec.is_empty())
+                    .map(|spec| spec.to_string())
+                    .collect::<HashSet<_>>();
+                if specs.is_empty() {
+                    anyhow::bail!("empty crate list: {}", s);
+                }
+                Ok(CrateSelect::List(specs))
+            }
+            s if s.starts_with("rdeps:") => {
+                let (crates, depth) = match s[6..].rsplit_once('@') {
+                    Some((crates, "all")) => (crates, None),
+                    Some((crates, depth)) => match depth.parse::<u32>() {
+                        Ok(depth) if depth > 0 => (crates, Some(depth)),
+                        _ => anyhow::bail!("invalid reverse dependencies depth: {}", s),
+                    },
+                    None => (&s[6..], Some(1)),
+                };
+                let crates = crates
+                    .split(',')
+                    .map(|krate| krate.trim())
+                    .filter(|krate| !krate.is_empty())
+                    .map(|krate| krate.to_string())
+                    .collect::<HashSet<_>>();
+                if crates.is_empty() {
+                    anyhow::bail!("no crates to select the reverse dependencies of: {}", s);
+                }
+                Ok(CrateSelect::ReverseDeps { crates, depth })
+            }
+            s if s.starts_with("ex:") => {
+                let (experiment, categories) = s[3..]
+                    .rsplit_once(':')
+                    .filter(|(experiment, _)| !experiment.is_empty())
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("expected ex:<experiment>:<categories>: {}", s)
+                    })?;
+                let categories = categories
+                    .split(',')
+                    .map(|category| category.trim())
+                    .filter(|category| !category.is_empty())
+                    .map(Comparison::from_str)
+                    .collect::<Fallible<HashSet<_>>>()?;
+                if categories.is_empty() {
+                    anyhow::bail!("no result categories to select crates from: {}", s);
+                }
+                Ok(CrateSelect::FromExperiment {
+                    experiment: experiment.to_string(),
+                    categories,
+                })
+            }
+            s if s.starts_with("file:") && s.len() > 5 => {
+                Ok(CrateSelect::File(PathBuf::from(&s[5..])))
+            }
             s if s.starts_with("random-") => {
-                let count = s[7..].parse::<u32>()
+                let (count, seed) = match s[7..].split_once('@') {
+                    Some((count, seed)) => (count, Some(seed)),
+                    None => (&s[7..], None),
+                };
+                let count = count.parse::<u32>()
                     .map_err(|_| anyhow::anyhow!("invalid random count: {}", s))?;
-                Ok(CrateSelect::Random(count))
+                let seed = seed
+                    .map(|seed| seed.parse::<u64>())
+                    .transpose()
+                    .map_err(|_| anyhow::anyhow!("invalid random seed: {}", s))?;
+                Ok(CrateSelect::Random { count, seed })
             }
             _ => anyhow::bail!("unknown crate select: {}", input),
         }
@@ -169,6 +342,7 @@ impl FromStr for Assignee {
 #[derive(Serialize, Deserialize)]
 pub struct ExperimentDBRecord {
     pub name: String,
+    pub kind: String,
     pub mode: String,
     pub cap_lints: String,
     pub toolchain_start: Option<String>,
@@ -185,6 +359,7 @@ pub struct ExperimentDBRecord {
     pub report_url: Option<String>,
     pub ignore_blacklist: bool,
     pub requirement: Option<String>,
+    pub crate_select: Option<String>,
 }
 
 #[cfg(test)]
@@ -227,6 +402,38 @@ mod tests {
         assert_eq!("fix".parse::<Mode>().unwrap(), Mode::Fix);
     }
 
+    #[test]
+    fn test_experiment_kind_validate() {
+        let tc = |s: &str| s.parse::<Toolchain>().unwrap();
+        let host = tc("stable");
+        let gnu = tc("stable+target=x86_64-unknown-linux-gnu");
+        let ohos = tc("stable+target=aarch64-unknown-linux-ohos");
+
+        assert_eq!(
+            "cross-target".parse::<ExperimentKind>().unwrap(),
+            ExperimentKind::CrossTarget
+        );
+        let kind = ExperimentKind::CrossTarget;
+        kind.validate(&[host.clone(), ohos.clone()]).unwrap();
+        kind.validate(&[gnu.clone(), ohos.clone()]).unwrap();
+
+        // Different compilers, or OHOS on the wrong side.
+        assert!(kind
+            .validate(&[host.clone(), tc("beta+target=aarch64-unknown-linux-ohos")])
+            .is_err());
+        assert!(kind.validate(&[ohos.clone(), gnu.clone()]).is_err());
+        assert!(kind.validate(&[host.clone(), gnu.clone()]).is_err());
+        assert!(kind
+            .validate(&[tc("stable+target=armv7-unknown-linux-ohos"), ohos.clone()])
+            .is_err());
+
+        let kind = ExperimentKind::ToolchainComparison;
+        kind.validate(&[host.clone(), tc("beta")]).unwrap();
+        kind.validate(&[host.clone(), tc("stable+rustflags=-Zsomething")])
+            .unwrap();
+        assert!(kind.validate(&[host.clone(), host]).is_err());
+    }
+
     #[test]
     fn test_cap_lints_parsing() {
         assert_eq!("allow".parse::<CapLints>().unwrap(), CapLints::Allow);
@@ -255,7 +462,93 @@ mod tests {
         assert_eq!("top-100".parse::<CrateSelect>().unwrap(), CrateSelect::Top(100));
         assert_eq!("local".parse::<CrateSelect>().unwrap(), CrateSelect::Local);
         assert_eq!("dummy".parse::<CrateSelect>().unwrap(), CrateSelect::Dummy);
-        assert_eq!("random-50".parse::<CrateSelect>().unwrap(), CrateSelect::Random(50));
+        assert_eq!(
+            "random-50".parse::<CrateSelect>().unwrap(),
+            CrateSelect::Random { count: 50, seed: None }
+        );
+        assert_eq!(
+            "random-50@42".parse::<CrateSelect>().unwrap(),
+            CrateSelect::Random { count: 50, seed: Some(42) }
+        );
+        assert!("random-50@x".parse::<CrateSelect>().is_err());
+    }
+
+    #[test]
+    fn test_crate_select_list() {
+        let select = "list:gh:rust-lang/libc, reg:serde-1.0.0,,".parse::<CrateSelect>().unwrap();
+        let CrateSelect::List(ref specs) = select else {
+            panic!("not a list: {:?}", select);
+        };
+        assert_eq!(specs.len(), 2);
+        assert!(specs.contains("gh:rust-lang/libc"));
+        assert_eq!(select.to_string(), "list:gh:rust-lang/libc,reg:serde-1.0.0");
+        assert_eq!(select.to_string().parse::<CrateSelect>().unwrap(), select);
+        assert!("list:".parse::<CrateSelect>().is_err());
+
+        assert_eq!(
+            "file:/tmp/crates.txt".parse::<CrateSelect>().unwrap(),
+            CrateSelect::File(PathBuf::from("/tmp/crates.txt"))
+        );
+        assert!("file:".parse::<CrateSelect>().is_err());
+    }
+
+    #[test]
+    fn test_crate_select_reverse_deps() {
+        let rdeps = |crates: &[&str], depth| CrateSelect::ReverseDeps {
+            crates: crates.iter().map(|c| c.to_string()).collect(),
+            depth,
+        };
+        assert_eq!(
+            "rdeps:libc".parse::<CrateSelect>().unwrap(),
+            rdeps(&["libc"], Some(1))
+        );
+        assert_eq!(
+            "rdeps:nix, libc@3".parse::<CrateSelect>().unwrap(),
+            rdeps(&["libc", "nix"], Some(3))
+        );
+        assert_eq!(
+            "rdeps:libc@all".parse::<CrateSelect>().unwrap(),
+            rdeps(&["libc"], None)
+        );
+        for select in ["rdeps:libc", "rdeps:libc,nix@3", "rdeps:libc@all"] {
+            assert_eq!(select.parse::<CrateSelect>().unwrap().to_string(), select);
+        }
+        assert!("rdeps:".parse::<CrateSelect>().is_err());
+        assert!("rdeps:libc@0".parse::<CrateSelect>().is_err());
+        assert!("rdeps:libc@x".parse::<CrateSelect>().is_err());
+    }
+
+    #[test]
+    fn test_crate_select_from_experiment() {
+        let select = "ex:pr-123:regressed, build-fail".parse::<CrateSelect>().unwrap();
+        assert_eq!(
+            select,
+            CrateSelect::FromExperiment {
+                experiment: "pr-123".to_string(),
+                categories: [Comparison::Regressed, Comparison::SameBuildFail]
+                    .into_iter()
+                    .collect(),
+            }
+        );
+        assert_eq!(select.to_string(), "ex:pr-123:build-fail,regressed");
+        assert_eq!(select.to_string().parse::<CrateSelect>().unwrap(), select);
+
+        assert!("ex:pr-123".parse::<CrateSelect>().is_err());
+        assert!("ex::regressed".parse::<CrateSelect>().is_err());
+        assert!("ex:pr-123:".parse::<CrateSelect>().is_err());
+        assert!("ex:pr-123:bogus".parse::<CrateSelect>().is_err());
+    }
+
+    #[test]
+    fn test_crate_select_with_seed() {
+        let select = "random-10".parse::<CrateSelect>().unwrap().with_seed();
+        let CrateSelect::Random { seed: Some(_), .. } = select else {
+            panic!("no seed was picked");
+        };
+        // The seed survives a round-trip through the database.
+        assert_eq!(select.to_string().parse::<CrateSelect>().unwrap(), select);
+        assert_eq!(select.clone().with_seed(), select);
+        assert_eq!(CrateSelect::Demo.with_seed(), CrateSelect::Demo);
     }
 
     #[test]
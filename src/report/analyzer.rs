@@ -1,6 +1,7 @@
 // Allow dead code for Phase 3 functions not yet fully connected
 #![allow(dead_code)]
 
+use crate::experiments::ExperimentKind;
 use crate::report::{Comparison, CrateResult, RawTestResults};
 use crate::results::TestResult;
 use std::collections::HashMap;
@@ -31,11 +32,14 @@ pub struct Summary {
     pub total: usize,
     pub regressed: usize,
     pub fixed: usize,
+    pub ohos_only_failure: usize,
+    pub ohos_only_pass: usize,
     pub broken: usize,
     pub same_build_fail: usize,
     pub same_test_fail: usize,
     pub same_test_pass: usize,
     pub same_test_skipped: usize,
+    pub same_test_not_runnable: usize,
     pub skipped: usize,
     pub unknown: usize,
     pub errors: usize,
@@ -49,11 +53,14 @@ impl TestResults {
             total: raw.crates.len(),
             regressed: 0,
             fixed: 0,
+            ohos_only_failure: 0,
+            ohos_only_pass: 0,
             broken: 0,
             same_build_fail: 0,
             same_test_fail: 0,
             same_test_pass: 0,
             same_test_skipped: 0,
+            same_test_not_runnable: 0,
             skipped: 0,
             unknown: 0,
             errors: 0,
@@ -64,11 +71,14 @@ impl TestResults {
             match crate_result.res {
                 Comparison::Regressed => summary.regressed += 1,
                 Comparison::Fixed => summary.fixed += 1,
+                Comparison::OhosOnlyFailure => summary.ohos_only_failure += 1,
+                Comparison::OhosOnlyPass => summary.ohos_only_pass += 1,
                 Comparison::Broken => summary.broken += 1,
                 Comparison::SameBuildFail => summary.same_build_fail += 1,
                 Comparison::SameTestFail => summary.same_test_fail += 1,
                 Comparison::SameTestPass => summary.same_test_pass += 1,
                 Comparison::SameTestSkipped => summary.same_test_skipped += 1,
+                Comparison::SameTestNotRunnable => summary.same_test_not_runnable += 1,
                 Comparison::Skipped => summary.skipped += 1,
                 Comparison::Unknown => summary.unknown += 1,
                 Comparison::Error => summary.errors += 1,
@@ -101,9 +111,40 @@ impl TestResults {
 
 /// Compare two test results and determine the comparison type
 pub fn compare_results(
+    kind: ExperimentKind,
     start: &Option<TestResult>,
     end: &Option<TestResult>,
 ) -> Comparison {
+    match kind {
+        ExperimentKind::ToolchainComparison => compare_toolchains(start, end),
+        ExperimentKind::CrossTarget => compare_targets(start, end),
+    }
+}
+
+/// Compare the host (`start`) and OpenHarmony (`end`) results of a crate.
+///
+/// Both runs use the same compiler, so a result differing between them is
+/// caused by the OpenHarmony target rather than a regression or a fix. Its
+/// tests only run on OpenHarmony with a target runner, so when they don't the
+/// crate is compared on whether it builds.
+fn compare_targets(start: &Option<TestResult>, end: &Option<TestResult>) -> Comparison {
+    use TestResult::*;
+    let built = |result: &TestResult| {
+        matches!(result, TestPass | TestFail(_) | TestSkipped | TestNotRunnable)
+    };
+    match (start, end) {
+        (Some(host), Some(BuildFail(_))) if built(host) => Comparison::OhosOnlyFailure,
+        (Some(BuildFail(_)), Some(ohos)) if built(ohos) => Comparison::OhosOnlyPass,
+        (Some(TestPass | TestFail(_)), Some(TestNotRunnable)) => Comparison::SameTestNotRunnable,
+        _ => match compare_toolchains(start, end) {
+            Comparison::Regressed => Comparison::OhosOnlyFailure,
+            Comparison::Fixed => Comparison::OhosOnlyPass,
+            comparison => comparison,
+        },
+    }
+}
+
+fn compare_toolchains(start: &Option<TestResult>, end: &Option<TestResult>) -> Comparison {
     match (start, end) {
         (None, None) => Comparison::Skipped,
         (None, Some(_)) | (Some(_), None) => Comparison::Unknown,
@@ -117,6 +158,7 @@ pub fn compare_results(
                 (BuildFail(_), BuildFail(_)) => Comparison::SameBuildFail,
                 (TestFail(_), TestFail(_)) => Comparison::SameTestFail,
                 (TestSkipped, TestSkipped) => Comparison::SameTestSkipped,
+                (TestNotRunnable, TestNotRunnable) => Comparison::SameTestNotRunnable,
 
                 // Broken crates
                 (BrokenCrate(_), _) | (_, BrokenCrate(_)) => Comparison::Broken,
@@ -133,6 +175,10 @@ pub fn compare_results(
                 // Fix: failed -> passed
                 (BuildFail(_), TestPass) | (TestFail(_), TestPass) => Comparison::Fixed,
 
+                // Build-only runs skip the tests of the crates that build
+                (TestSkipped, BuildFail(_)) => Comparison::Regressed,
+                (BuildFail(_), TestSkipped) => Comparison::Fixed,
+
                 // Skipped
                 (Skipped, _) | (_, Skipped) => Comparison::Skipped,
 
@@ -152,6 +198,7 @@ mod tests {
     #[test]
     fn test_compare_results_both_pass() {
         let result = compare_results(
+            ExperimentKind::ToolchainComparison,
             &Some(TestResult::TestPass),
             &Some(TestResult::TestPass),
         );
@@ -161,6 +208,7 @@ mod tests {
     #[test]
     fn test_compare_results_regressed() {
         let result = compare_results(
+            ExperimentKind::ToolchainComparison,
             &Some(TestResult::TestPass),
             &Some(TestResult::BuildFail(FailureReason::Unknown)),
         );
@@ -170,24 +218,119 @@ mod tests {
     #[test]
     fn test_compare_results_fixed() {
         let result = compare_results(
+            ExperimentKind::ToolchainComparison,
             &Some(TestResult::BuildFail(FailureReason::Unknown)),
             &Some(TestResult::TestPass),
         );
         assert_eq!(result, Comparison::Fixed);
     }
 
+    #[test]
+    fn test_compare_results_build_only_regressed() {
+        let result = compare_results(
+            ExperimentKind::ToolchainComparison,
+            &Some(TestResult::TestSkipped),
+            &Some(TestResult::BuildFail(FailureReason::Unknown)),
+        );
+        assert_eq!(result, Comparison::Regressed);
+    }
+
+    #[test]
+    fn test_compare_results_build_only_fixed() {
+        let result = compare_results(
+            ExperimentKind::ToolchainComparison,
+            &Some(TestResult::BuildFail(FailureReason::Unknown)),
+            &Some(TestResult::TestSkipped),
+        );
+        assert_eq!(result, Comparison::Fixed);
+    }
+
     #[test]
     fn test_compare_results_same_build_fail() {
         let result = compare_results(
+            ExperimentKind::ToolchainComparison,
             &Some(TestResult::BuildFail(FailureReason::Unknown)),
             &Some(TestResult::BuildFail(FailureReason::Timeout)),
         );
         assert_eq!(result, Comparison::SameBuildFail);
     }
 
+    #[test]
+    fn test_compare_results_not_runnable() {
+        let result = compare_results(
+            ExperimentKind::ToolchainComparison,
+            &Some(TestResult::TestNotRunnable),
+            &Some(TestResult::TestNotRunnable),
+        );
+        assert_eq!(result, Comparison::SameTestNotRunnable);
+
+        // A crate whose tests can only run with one toolchain can't regress.
+        let result = compare_results(
+            ExperimentKind::ToolchainComparison,
+            &Some(TestResult::TestPass),
+            &Some(TestResult::TestNotRunnable),
+        );
+        assert_eq!(result, Comparison::Unknown);
+    }
+
+    #[test]
+    fn test_compare_results_cross_target() {
+        let kind = ExperimentKind::CrossTarget;
+        let result = compare_results(
+            kind,
+            &Some(TestResult::TestPass),
+            &Some(TestResult::BuildFail(FailureReason::Unknown)),
+        );
+        assert_eq!(result, Comparison::OhosOnlyFailure);
+
+        let result = compare_results(
+            kind,
+            &Some(TestResult::TestFail(FailureReason::Unknown)),
+            &Some(TestResult::TestPass),
+        );
+        assert_eq!(result, Comparison::OhosOnlyPass);
+
+        let result = compare_results(
+            kind,
+            &Some(TestResult::TestPass),
+            &Some(TestResult::TestPass),
+        );
+        assert_eq!(result, Comparison::SameTestPass);
+    }
+
+    #[test]
+    fn test_compare_results_cross_target_runner_modes() {
+        use TestResult::*;
+        let kind = ExperimentKind::CrossTarget;
+        let fail = || BuildFail(FailureReason::Unknown);
+
+        // Build-only runs skip the tests of the crates that build.
+        let cases = [
+            (TestSkipped, fail(), Comparison::OhosOnlyFailure),
+            (fail(), TestSkipped, Comparison::OhosOnlyPass),
+            (TestSkipped, TestSkipped, Comparison::SameTestSkipped),
+            (fail(), fail(), Comparison::SameBuildFail),
+        ];
+        for (host, ohos, expected) in cases {
+            assert_eq!(compare_results(kind, &Some(host), &Some(ohos)), expected);
+        }
+
+        // Without a target runner, the OpenHarmony tests are built but not run.
+        let cases = [
+            (TestPass, TestNotRunnable, Comparison::SameTestNotRunnable),
+            (TestFail(FailureReason::Unknown), TestNotRunnable, Comparison::SameTestNotRunnable),
+            (fail(), TestNotRunnable, Comparison::OhosOnlyPass),
+            (TestPass, fail(), Comparison::OhosOnlyFailure),
+            (TestFail(FailureReason::Unknown), fail(), Comparison::OhosOnlyFailure),
+        ];
+        for (host, ohos, expected) in cases {
+            assert_eq!(compare_results(kind, &Some(host), &Some(ohos)), expected);
+        }
+    }
+
     #[test]
     fn test_compare_results_skipped() {
-        let result = compare_results(&None, &None);
+        let result = compare_results(ExperimentKind::ToolchainComparison, &None, &None);
         assert_eq!(result, Comparison::Skipped);
     }
 
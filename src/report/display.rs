@@ -39,6 +39,7 @@ impl ResultColor for TestResult {
         match self {
             TestResult::TestPass => Color::Green,
             TestResult::TestSkipped => Color::Yellow,
+            TestResult::TestNotRunnable => Color::Yellow,
             TestResult::Skipped => Color::Yellow,
             TestResult::BuildFail(_) => Color::Red,
             TestResult::TestFail(_) => Color::Red,
@@ -59,6 +60,7 @@ impl ResultName for TestResult {
         match self {
             TestResult::TestPass => "test-pass",
             TestResult::TestSkipped => "test-skipped",
+            TestResult::TestNotRunnable => "test-not-runnable",
             TestResult::Skipped => "skipped",
             TestResult::BuildFail(_) => "build-fail",
             TestResult::TestFail(_) => "test-fail",
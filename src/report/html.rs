@@ -36,11 +36,14 @@ mod tests {
                 total: 100,
                 regressed: 5,
                 fixed: 3,
+                ohos_only_failure: 0,
+                ohos_only_pass: 0,
                 broken: 2,
                 same_build_fail: 10,
                 same_test_fail: 5,
                 same_test_pass: 70,
                 same_test_skipped: 3,
+                same_test_not_runnable: 0,
                 skipped: 2,
                 unknown: 0,
                 errors: 0,
@@ -10,6 +10,16 @@ pub fn generate_markdown_report(results: &TestResults) -> Fallible<String> {
     output.push_str(&format!("- Total crates: {}\n", results.summary.total));
     output.push_str(&format!("- Regressions: {}\n", results.summary.regressed));
     output.push_str(&format!("- Fixes: {}\n", results.summary.fixed));
+    if results.summary.ohos_only_failure + results.summary.ohos_only_pass > 0 {
+        output.push_str(&format!(
+            "- OHOS-only failures: {}\n",
+            results.summary.ohos_only_failure
+        ));
+        output.push_str(&format!(
+            "- OHOS-only passes: {}\n",
+            results.summary.ohos_only_pass
+        ));
+    }
     output.push_str(&format!("- Broken: {}\n", results.summary.broken));
     output.push_str(&format!(
         "- Same build failures: {}\n",
@@ -23,8 +33,26 @@ pub fn generate_markdown_report(results: &TestResults) -> Fallible<String> {
         "- Same test passes: {}\n",
         results.summary.same_test_pass
     ));
+    output.push_str(&format!(
+        "- Tests not runnable: {}\n",
+        results.summary.same_test_not_runnable
+    ));
     output.push_str(&format!("- Skipped: {}\n", results.summary.skipped));
 
+    // Git crates are listed with the commit both toolchains built.
+    let mut categories = results
+        .categories
+        .iter()
+        .filter(|(comparison, _)| comparison.show_in_summary())
+        .collect::<Vec<_>>();
+    categories.sort_by_key(|(comparison, _)| comparison.to_str());
+    for (comparison, crates) in categories {
+        output.push_str(&format!("\n## {} ({})\n\n", comparison, crates.len()));
+        for krate in crates {
+            output.push_str(&format!("- `{}`\n", krate.krate));
+        }
+    }
+
     Ok(output)
 }
 
@@ -42,11 +70,14 @@ mod tests {
                 total: 100,
                 regressed: 5,
                 fixed: 3,
+                ohos_only_failure: 0,
+                ohos_only_pass: 0,
                 broken: 2,
                 same_build_fail: 10,
                 same_test_fail: 5,
                 same_test_pass: 70,
                 same_test_skipped: 3,
+                same_test_not_runnable: 0,
                 skipped: 2,
                 unknown: 0,
                 errors: 0,
@@ -59,4 +90,28 @@ mod tests {
         assert!(markdown.contains("Regressions: 5"));
         assert!(markdown.contains("Fixes: 3"));
     }
+
+    #[test]
+    fn test_markdown_report_lists_crates() {
+        use crate::report::{Comparison, CrateResult, RawTestResults};
+
+        let result = |spec: &str, res| CrateResult {
+            name: spec.to_string(),
+            url: String::new(),
+            krate: spec.parse().unwrap(),
+            status: None,
+            res,
+            runs: [None, None],
+        };
+        let results = TestResults::from_raw(RawTestResults {
+            crates: vec![
+                result("gh:rust-lang/libc#abc123", Comparison::Regressed),
+                result("reg:serde-1.0.0", Comparison::SameTestPass),
+            ],
+        });
+
+        let markdown = generate_markdown_report(&results).unwrap();
+        assert!(markdown.contains("## regressed (1)\n\n- `gh:rust-lang/libc#abc123`\n"));
+        assert!(!markdown.contains("serde"));
+    }
 }
@@ -15,7 +15,7 @@ mod html;
 mod markdown;
 
 pub use self::display::{Color, ResultColor, ResultName};
-pub use analyzer::TestResults;
+pub use analyzer::{compare_results, TestResults};
 
 /// Raw test results before analysis
 #[derive(Serialize, Deserialize)]
@@ -53,9 +53,12 @@ string_enum! {
         SameBuildFail => "build-fail",
         SameTestFail => "test-fail",
         SameTestSkipped => "test-skipped",
+        SameTestNotRunnable => "test-not-runnable",
         SameTestPass => "test-pass",
         SpuriousRegressed => "spurious-regressed",
         SpuriousFixed => "spurious-fixed",
+        OhosOnlyFailure => "ohos-only-failure",
+        OhosOnlyPass => "ohos-only-pass",
     }
 }
 
@@ -65,7 +68,12 @@ impl Comparison {
     pub fn show_in_summary(&self) -> bool {
         matches!(
             self,
-            Comparison::Regressed | Comparison::Fixed | Comparison::SpuriousRegressed | Comparison::SpuriousFixed
+            Comparison::Regressed
+                | Comparison::Fixed
+                | Comparison::SpuriousRegressed
+                | Comparison::SpuriousFixed
+                | Comparison::OhosOnlyFailure
+                | Comparison::OhosOnlyPass
         )
     }
 }
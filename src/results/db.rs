@@ -1,8 +1,9 @@
 use crate::crates::Crate;
 use crate::db::DatabasePool;
 use crate::prelude::*;
-use crate::results::{EncodedLog, TestResult};
+use crate::results::{EncodedLog, EncodingType, ResolvedPatches, TestResult};
 use crate::toolchain::Toolchain;
+use rusqlite::OptionalExtension;
 
 pub struct DatabaseDB {
     pool: DatabasePool,
@@ -20,23 +21,80 @@ impl DatabaseDB {
         toolchain: &Toolchain,
         result: &TestResult,
         log: Option<&EncodedLog>,
+    ) -> Fallible<()> {
+        self.store_result_with_patches(
+            experiment,
+            krate,
+            toolchain,
+            result,
+            log,
+            &ResolvedPatches::new(),
+        )
+    }
+
+    /// Store the result of `krate` together with the commits the patches of
+    /// `toolchain` resolved to, in a single statement so that neither is
+    /// saved without the other.
+    pub fn store_result_with_patches(
+        &self,
+        experiment: &str,
+        krate: &Crate,
+        toolchain: &Toolchain,
+        result: &TestResult,
+        log: Option<&EncodedLog>,
+        patches: &ResolvedPatches,
     ) -> Fallible<()> {
         let conn = self.pool.get()?;
 
         let krate_str = krate.to_string();
-        let toolchain_str = toolchain.to_string();
+        let toolchain_str = toolchain.id();
         let result_json = serde_json::to_string(result)?;
         let log_bytes = log.map(|l| l.to_bytes().to_vec());
+        let encoding = log.map_or(EncodingType::Plain, |l| l.encoding_type());
+        let patches_json = if patches.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(patches)?)
+        };
 
         conn.execute(
-            "INSERT OR REPLACE INTO results (experiment, crate, toolchain, result, log) 
-             VALUES (?, ?, ?, ?, ?)",
-            rusqlite::params![experiment, krate_str, toolchain_str, result_json, log_bytes],
+            "INSERT OR REPLACE INTO results (experiment, crate, toolchain, result, log, encoding, patches) 
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            rusqlite::params![
+                experiment,
+                krate_str,
+                toolchain_str,
+                result_json,
+                log_bytes,
+                encoding.to_str(),
+                patches_json
+            ],
         )?;
 
         Ok(())
     }
 
+    pub fn get_patches(
+        &self,
+        experiment: &str,
+        krate: &Crate,
+        toolchain: &Toolchain,
+    ) -> Fallible<ResolvedPatches> {
+        let conn = self.pool.get()?;
+        let patches: Option<String> = conn
+            .query_row(
+                "SELECT patches FROM results WHERE experiment = ? AND crate = ? AND toolchain = ?",
+                rusqlite::params![experiment, krate.to_string(), toolchain.id()],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+        match patches {
+            Some(patches) => Ok(serde_json::from_str(&patches)?),
+            None => Ok(ResolvedPatches::new()),
+        }
+    }
+
     pub fn get_result(
         &self,
         experiment: &str,
@@ -46,22 +104,25 @@ impl DatabaseDB {
         let conn = self.pool.get()?;
 
         let krate_str = krate.to_string();
-        let toolchain_str = toolchain.to_string();
+        let toolchain_str = toolchain.id();
 
         let result = conn.query_row(
-            "SELECT result, log FROM results WHERE experiment = ? AND crate = ? AND toolchain = ?",
+            "SELECT result, log, encoding FROM results \
+             WHERE experiment = ? AND crate = ? AND toolchain = ?",
             rusqlite::params![experiment, krate_str, toolchain_str],
             |row| {
                 let result_json: String = row.get(0)?;
                 let log_bytes: Option<Vec<u8>> = row.get(1)?;
-                Ok((result_json, log_bytes))
+                let encoding: String = row.get(2)?;
+                Ok((result_json, log_bytes, encoding))
             },
         );
 
         match result {
-            Ok((result_json, log_bytes)) => {
+            Ok((result_json, log_bytes, encoding)) => {
                 let test_result: TestResult = serde_json::from_str(&result_json)?;
-                let log = log_bytes.map(EncodedLog::from_plain);
+                let encoding: EncodingType = encoding.parse()?;
+                let log = log_bytes.map(|bytes| EncodedLog::from_encoding(encoding, bytes));
                 Ok(Some((test_result, log)))
             }
             Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
@@ -123,22 +184,27 @@ impl Default for ProgressData {
 mod tests {
     use super::*;
     use crate::crates::sources::registry::RegistryCrate;
-    use crate::db::create_memory_pool;
+    use crate::db::{create_memory_pool, DatabasePool};
     use crate::toolchain::RustwideToolchain;
 
+    /// Create a dummy experiment, to satisfy the foreign key of the results.
+    fn insert_experiment(pool: &DatabasePool, name: &str) {
+        pool.get()
+            .unwrap()
+            .execute(
+                "INSERT INTO experiments (name, mode, cap_lints, priority, created_at, status, ignore_blacklist)
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+                rusqlite::params![name, "build-and-test", "warn", 0, "2024-01-01 00:00:00", "queued", 0],
+            )
+            .unwrap();
+    }
+
     #[test]
     fn test_store_and_get_result() {
         let pool = create_memory_pool().unwrap();
         let db = DatabaseDB::new(pool.clone());
 
-        // Create a dummy experiment first to satisfy foreign key constraint
-        let conn = pool.get().unwrap();
-        conn.execute(
-            "INSERT INTO experiments (name, mode, cap_lints, priority, created_at, status, ignore_blacklist)
-             VALUES (?, ?, ?, ?, ?, ?, ?)",
-            rusqlite::params!["exp1", "build-and-test", "warn", 0, "2024-01-01 00:00:00", "queued", 0],
-        ).unwrap();
-        drop(conn);
+        insert_experiment(&pool, "exp1");
 
         let krate = Crate::Registry(RegistryCrate::new("test", "1.0.0"));
         let toolchain = Toolchain {
@@ -165,6 +231,88 @@ mod tests {
         assert_eq!(retrieved_log.unwrap().decode().unwrap(), "test log");
     }
 
+    #[test]
+    fn test_store_and_get_gzip_result() {
+        let pool = create_memory_pool().unwrap();
+        let db = DatabaseDB::new(pool.clone());
+
+        insert_experiment(&pool, "exp1");
+
+        let krate = Crate::Registry(RegistryCrate::new("test", "1.0.0"));
+        let toolchain: Toolchain = "stable".parse().unwrap();
+        let log = EncodedLog::compress(b"compressed log").unwrap();
+
+        db.store_result("exp1", &krate, &toolchain, &TestResult::TestPass, Some(&log))
+            .unwrap();
+
+        let (_, retrieved_log) = db.get_result("exp1", &krate, &toolchain).unwrap().unwrap();
+        let retrieved_log = retrieved_log.unwrap();
+        assert_eq!(retrieved_log.encoding_type(), EncodingType::Gzip);
+        assert_eq!(retrieved_log.decode().unwrap(), "compressed log");
+    }
+
+    #[test]
+    fn test_results_keyed_by_full_toolchain() {
+        let pool = create_memory_pool().unwrap();
+        let db = DatabaseDB::new(pool.clone());
+
+        insert_experiment(&pool, "exp1");
+
+        // Toolchains differing only in their flags keep separate results.
+        let krate = Crate::Registry(RegistryCrate::new("test", "1.0.0"));
+        let plain: Toolchain = "stable".parse().unwrap();
+        let flags: Toolchain = "stable+rustflags=-Zsomething".parse().unwrap();
+        db.store_result("exp1", &krate, &plain, &TestResult::TestPass, None)
+            .unwrap();
+        db.store_result("exp1", &krate, &flags, &TestResult::TestSkipped, None)
+            .unwrap();
+
+        let (result, _) = db.get_result("exp1", &krate, &plain).unwrap().unwrap();
+        assert_eq!(result, TestResult::TestPass);
+        let (result, _) = db.get_result("exp1", &krate, &flags).unwrap().unwrap();
+        assert_eq!(result, TestResult::TestSkipped);
+    }
+
+    #[test]
+    fn test_store_and_get_patches() {
+        let pool = create_memory_pool().unwrap();
+        let db = DatabaseDB::new(pool.clone());
+
+        insert_experiment(&pool, "exp1");
+
+        let krate = Crate::Registry(RegistryCrate::new("test", "1.0.0"));
+        let toolchain: Toolchain = "stable+patch=libc=https://gitee.com/openharmony/libc#ohos"
+            .parse()
+            .unwrap();
+        db.store_result("exp1", &krate, &toolchain, &TestResult::TestPass, None)
+            .unwrap();
+        assert!(db
+            .get_patches("exp1", &krate, &toolchain)
+            .unwrap()
+            .is_empty());
+
+        let mut patches = ResolvedPatches::new();
+        patches.insert("libc".to_string(), "0123456789abcdef".to_string());
+        db.store_result_with_patches(
+            "exp1",
+            &krate,
+            &toolchain,
+            &TestResult::TestPass,
+            None,
+            &patches,
+        )
+        .unwrap();
+        assert_eq!(db.get_patches("exp1", &krate, &toolchain).unwrap(), patches);
+
+        // Storing the result again replaces its patches too.
+        db.store_result("exp1", &krate, &toolchain, &TestResult::TestSkipped, None)
+            .unwrap();
+        assert!(db
+            .get_patches("exp1", &krate, &toolchain)
+            .unwrap()
+            .is_empty());
+    }
+
     #[test]
     fn test_get_nonexistent_result() {
         let pool = create_memory_pool().unwrap();
@@ -190,14 +338,7 @@ mod tests {
         let pool = create_memory_pool().unwrap();
         let db = DatabaseDB::new(pool.clone());
 
-        // Create a dummy experiment first to satisfy foreign key constraint
-        let conn = pool.get().unwrap();
-        conn.execute(
-            "INSERT INTO experiments (name, mode, cap_lints, priority, created_at, status, ignore_blacklist)
-             VALUES (?, ?, ?, ?, ?, ?, ?)",
-            rusqlite::params!["exp1", "build-and-test", "warn", 0, "2024-01-01 00:00:00", "queued", 0],
-        ).unwrap();
-        drop(conn);
+        insert_experiment(&pool, "exp1");
 
         let krate = Crate::Registry(RegistryCrate::new("test", "1.0.0"));
         let toolchain = Toolchain {
@@ -5,13 +5,17 @@ mod dummy;
 use crate::crates::Crate;
 use crate::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::str::FromStr;
 
 pub use crate::results::db::{DatabaseDB, ProgressData};
 #[cfg(test)]
 pub use crate::results::dummy::DummyDB;
 
+/// Commit each crate patched by a toolchain resolved to in a build, by crate
+/// name. Patches the crate doesn't depend on are left out.
+pub type ResolvedPatches = BTreeMap<String, String>;
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum TestResult {
@@ -20,6 +24,9 @@ pub enum TestResult {
     BuildFail(FailureReason),
     TestFail(FailureReason),
     TestSkipped,
+    /// The tests were built, but can't be executed for the target of the
+    /// toolchain, e.g. because no runner is configured for it.
+    TestNotRunnable,
     TestPass,
     Skipped,
     Error,
@@ -55,6 +62,7 @@ impl FromStr for TestResult {
             match s {
                 "test-pass" => Ok(TestResult::TestPass),
                 "test-skipped" => Ok(TestResult::TestSkipped),
+                "test-not-runnable" => Ok(TestResult::TestNotRunnable),
                 "skipped" => Ok(TestResult::Skipped),
                 "error" => Ok(TestResult::Error),
                 _ => anyhow::bail!("unknown test result: {}", s),
@@ -113,6 +121,7 @@ pub enum BrokenReason {
     Yanked,
     MissingDependencies,
     MissingGitRepository,
+    MissingSource,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -167,6 +176,24 @@ impl EncodedLog {
         EncodedLog::Gzip(data)
     }
 
+    /// Compress a plain log into `EncodedLog::Gzip`.
+    pub fn compress(data: &[u8]) -> Fallible<Self> {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        Ok(EncodedLog::Gzip(encoder.finish()?))
+    }
+
+    pub fn from_encoding(encoding: EncodingType, data: Vec<u8>) -> Self {
+        match encoding {
+            EncodingType::Plain => EncodedLog::from_plain(data),
+            EncodingType::Gzip => EncodedLog::from_gzip(data),
+        }
+    }
+
     pub fn decode(&self) -> Fallible<String> {
         match self {
             EncodedLog::Plain(data) => Ok(String::from_utf8_lossy(data).to_string()),
@@ -198,6 +225,18 @@ impl EncodingType {
     }
 }
 
+impl FromStr for EncodingType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "plain" => Ok(EncodingType::Plain),
+            "gzip" => Ok(EncodingType::Gzip),
+            _ => anyhow::bail!("unknown log encoding: {}", s),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -299,4 +338,17 @@ mod tests {
         assert_eq!(plain.to_plain().unwrap(), b"test log");
         assert_eq!(plain.get_encoding_type(), EncodingType::Plain);
     }
+
+    #[test]
+    fn test_encoded_log_compress() {
+        let log = EncodedLog::compress(b"test log").unwrap();
+        assert_eq!(log.encoding_type(), EncodingType::Gzip);
+        assert_ne!(log.to_bytes(), b"test log");
+        assert_eq!(log.to_plain().unwrap(), b"test log");
+
+        let encoding = EncodingType::from_str(log.encoding_type().to_str()).unwrap();
+        let log = EncodedLog::from_encoding(encoding, log.to_bytes().to_vec());
+        assert_eq!(log.decode().unwrap(), "test log");
+        assert!(EncodingType::from_str("brotli").is_err());
+    }
 }
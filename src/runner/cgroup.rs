@@ -0,0 +1,127 @@
+use crate::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A cgroup v2 created for a single command, capping the memory of every
+/// process it spawns. The cgroup is removed when dropped.
+pub(super) struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    /// Whether `root` is a cgroup v2 directory with the memory controller
+    /// enabled for its children.
+    pub(super) fn is_supported(root: &Path) -> bool {
+        fs::read_to_string(root.join("cgroup.subtree_control"))
+            .map(|controllers| controllers.split_whitespace().any(|c| c == "memory"))
+            .unwrap_or(false)
+    }
+
+    pub(super) fn create(root: &Path, memory_limit: u64) -> Fallible<Self> {
+        let name = format!(
+            "crater-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::SeqCst)
+        );
+        let path = root.join(name);
+        fs::create_dir(&path)
+            .with_context(|| format!("failed to create cgroup {}", path.display()))?;
+
+        let cgroup = Cgroup { path };
+        fs::write(cgroup.path.join("memory.max"), memory_limit.to_string())?;
+        // Without swap the limit is enforced by the OOM killer right away
+        // instead of slowing the build down to a crawl. Not every kernel
+        // exposes this file, so failures are ignored.
+        let _ = fs::write(cgroup.path.join("memory.swap.max"), "0");
+        Ok(cgroup)
+    }
+
+    /// File a process writes `0` into to move itself into the cgroup.
+    pub(super) fn procs_file(&self) -> PathBuf {
+        self.path.join("cgroup.procs")
+    }
+
+    /// Whether the kernel OOM killer killed a process of this cgroup.
+    pub(super) fn oom_killed(&self) -> bool {
+        fs::read_to_string(self.path.join("memory.events"))
+            .map(|events| parse_oom_kills(&events) > 0)
+            .unwrap_or(false)
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        // A cgroup can only be removed with rmdir once it has no processes;
+        // the regular files written by `create` only exist in tests.
+        for file in [
+            "memory.max",
+            "memory.swap.max",
+            "memory.events",
+            "cgroup.procs",
+        ] {
+            let _ = fs::remove_file(self.path.join(file));
+        }
+        if let Err(err) = fs::remove_dir(&self.path) {
+            warn!("failed to remove cgroup {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+fn parse_oom_kills(events: &str) -> u64 {
+    events
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .find(|(key, _)| *key == "oom_kill")
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_oom_kills() {
+        assert_eq!(
+            parse_oom_kills("low 0\nhigh 0\nmax 3\noom 1\noom_kill 1\n"),
+            1
+        );
+        assert_eq!(parse_oom_kills("low 0\noom_kill 0\n"), 0);
+        assert_eq!(parse_oom_kills(""), 0);
+    }
+
+    #[test]
+    fn test_is_supported() {
+        let dir = tempdir().unwrap();
+        assert!(!Cgroup::is_supported(dir.path()));
+
+        fs::write(dir.path().join("cgroup.subtree_control"), "cpu io\n").unwrap();
+        assert!(!Cgroup::is_supported(dir.path()));
+
+        fs::write(
+            dir.path().join("cgroup.subtree_control"),
+            "cpu memory pids\n",
+        )
+        .unwrap();
+        assert!(Cgroup::is_supported(dir.path()));
+    }
+
+    #[test]
+    fn test_create_and_remove() {
+        let dir = tempdir().unwrap();
+        let cgroup = Cgroup::create(dir.path(), 1024).unwrap();
+        let path = cgroup.path.clone();
+        assert_eq!(fs::read_to_string(path.join("memory.max")).unwrap(), "1024");
+        assert!(!cgroup.oom_killed());
+
+        fs::write(path.join("memory.events"), "oom 1\noom_kill 1\n").unwrap();
+        assert!(cgroup.oom_killed());
+
+        drop(cgroup);
+        assert!(!path.exists());
+    }
+}
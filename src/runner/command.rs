@@ -0,0 +1,509 @@
+use crate::prelude::*;
+use crate::runner::cgroup::Cgroup;
+use crate::runner::logs::LogStorage;
+use crate::runner::sandbox::{self, Sandbox};
+use std::collections::VecDeque;
+use std::ffi::OsStr;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command as StdCommand, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of trailing output lines embedded in a [`CommandError`].
+const ERROR_OUTPUT_LINES: usize = 20;
+
+#[derive(Debug, thiserror::Error)]
+pub(super) enum CommandError {
+    #[error("failed to spawn `{command}`: {source}")]
+    Spawn {
+        command: String,
+        source: std::io::Error,
+    },
+    #[error("command `{command}` failed with {status}")]
+    ExecutionFailed {
+        command: String,
+        status: ExitStatus,
+        output: String,
+    },
+    #[error("command `{command}` timed out after {timeout:?}")]
+    Timeout { command: String, timeout: Duration },
+    #[error("command `{command}` exceeded the memory limit of {limit} bytes")]
+    OutOfMemory { command: String, limit: u64 },
+    #[error("command `{command}` tried to access the network: {evidence}")]
+    NetworkAccess { command: String, evidence: String },
+}
+
+/// Resource limits applied to a command and every process it spawns.
+#[derive(Clone, Default)]
+pub(super) struct Limits {
+    /// Wall-clock time after which the whole process group is killed.
+    pub(super) timeout: Option<Duration>,
+    /// Maximum memory, in bytes.
+    pub(super) memory: Option<u64>,
+    /// cgroup v2 directory under which a cgroup enforcing `memory` is
+    /// created. Without it, `memory` falls back to `RLIMIT_AS`, which only
+    /// limits each process separately.
+    pub(super) cgroup_root: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// A child process whose combined output is captured into a [`LogStorage`].
+pub(super) struct Command {
+    inner: StdCommand,
+    description: String,
+    limits: Limits,
+    sandbox: Option<Sandbox>,
+}
+
+impl Command {
+    pub(super) fn new<S: AsRef<OsStr>>(program: S) -> Self {
+        Command {
+            description: program.as_ref().to_string_lossy().into_owned(),
+            inner: StdCommand::new(program),
+            limits: Limits::default(),
+            sandbox: None,
+        }
+    }
+
+    pub(super) fn arg<S: AsRef<OsStr>>(mut self, arg: S) -> Self {
+        self.description.push(' ');
+        self.description.push_str(&arg.as_ref().to_string_lossy());
+        self.inner.arg(arg);
+        self
+    }
+
+    pub(super) fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        for arg in args {
+            self = self.arg(arg);
+        }
+        self
+    }
+
+    pub(super) fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(mut self, key: K, value: V) -> Self {
+        self.inner.env(key, value);
+        self
+    }
+
+    pub(super) fn cwd<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.inner.current_dir(path);
+        self
+    }
+
+    pub(super) fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Run the command without network access, see [`Sandbox`].
+    pub(super) fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    pub(super) fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Run the command, storing every output line in `logs`.
+    pub(super) fn run(self, logs: &LogStorage) -> Fallible<()> {
+        self.run_with(logs, |_, line| Some(line.to_string()))
+    }
+
+    /// Run the command, passing every output line through `process` before
+    /// it is stored in `logs`. Returning `None` drops the line.
+    pub(super) fn run_with<F>(mut self, logs: &LogStorage, mut process: F) -> Fallible<()>
+    where
+        F: FnMut(Stream, &str) -> Option<String>,
+    {
+        logs.write_line(&format!("[INFO] running `{}`", self.description));
+
+        let cgroup = match (self.limits.memory, &self.limits.cgroup_root) {
+            (Some(limit), Some(root)) if Cgroup::is_supported(root) => {
+                Some(Cgroup::create(root, limit)?)
+            }
+            _ => None,
+        };
+        self.apply_limits(cgroup.as_ref())?;
+        // The cgroup must be joined before the sandbox leaves the initial
+        // user namespace, so the sandbox is applied last.
+        if let Some(sandbox) = &self.sandbox {
+            sandbox.apply(&mut self.inner)?;
+        }
+
+        let mut child = self
+            .inner
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|source| CommandError::Spawn {
+                command: self.description.clone(),
+                source,
+            })?;
+
+        let (tx, rx) = mpsc::channel();
+        let stdout = child
+            .stdout
+            .take()
+            .map(|out| forward_lines(out, Stream::Stdout, tx.clone()));
+        let stderr = child
+            .stderr
+            .take()
+            .map(|err| forward_lines(err, Stream::Stderr, tx));
+
+        let deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        let mut timed_out = false;
+        let mut allocation_failed = false;
+        let mut network_access = None;
+        let mut tail = VecDeque::with_capacity(ERROR_OUTPUT_LINES);
+        loop {
+            let (stream, line) = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    match rx.recv_timeout(remaining) {
+                        Ok(message) => message,
+                        Err(mpsc::RecvTimeoutError::Timeout) => {
+                            timed_out = true;
+                            kill_process_group(child.id());
+                            break;
+                        }
+                        Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                None => match rx.recv() {
+                    Ok(message) => message,
+                    Err(_) => break,
+                },
+            };
+
+            allocation_failed |= is_allocation_failure(&line);
+            if self.sandbox.is_some()
+                && network_access.is_none()
+                && sandbox::is_network_access(&line)
+            {
+                network_access = Some(line.clone());
+            }
+            if let Some(line) = process(stream, &line) {
+                logs.write_line(&line);
+                if tail.len() == ERROR_OUTPUT_LINES {
+                    tail.pop_front();
+                }
+                tail.push_back(line);
+            }
+        }
+
+        // After a timeout, processes that escaped the process group may still
+        // hold the pipes open: leave the reader threads behind in that case.
+        if !timed_out {
+            for handle in stdout.into_iter().chain(stderr) {
+                let _ = handle.join();
+            }
+        }
+
+        let status = child.wait()?;
+        if timed_out {
+            let timeout = self.limits.timeout.unwrap_or_default();
+            logs.write_line(&format!("[ERROR] timed out after {:?}", timeout));
+            return Err(CommandError::Timeout {
+                command: self.description,
+                timeout,
+            }
+            .into());
+        }
+
+        if status.success() {
+            return Ok(());
+        }
+
+        let out_of_memory = match (&cgroup, self.limits.memory) {
+            (Some(cgroup), _) => cgroup.oom_killed(),
+            (None, Some(_)) => allocation_failed,
+            (None, None) => false,
+        };
+        if out_of_memory {
+            let limit = self.limits.memory.unwrap_or_default();
+            logs.write_line(&format!(
+                "[ERROR] exceeded the memory limit of {} bytes",
+                limit
+            ));
+            return Err(CommandError::OutOfMemory {
+                command: self.description,
+                limit,
+            }
+            .into());
+        }
+
+        if let Some(evidence) = network_access {
+            logs.write_line(&format!(
+                "[ERROR] network access blocked by the sandbox: {}",
+                evidence
+            ));
+            return Err(CommandError::NetworkAccess {
+                command: self.description,
+                evidence,
+            }
+            .into());
+        }
+
+        Err(CommandError::ExecutionFailed {
+            command: self.description,
+            status,
+            output: Vec::from(tail).join("\n"),
+        }
+        .into())
+    }
+
+    /// Run the child in its own process group, so that it can be killed
+    /// together with everything it spawned, and confine it to the memory
+    /// limit before it executes.
+    #[cfg(unix)]
+    fn apply_limits(&mut self, cgroup: Option<&Cgroup>) -> Fallible<()> {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::process::CommandExt;
+
+        self.inner.process_group(0);
+
+        if let Some(cgroup) = cgroup {
+            let procs = CString::new(cgroup.procs_file().as_os_str().as_bytes())?;
+            // SAFETY: the closure only performs async-signal-safe syscalls on
+            // memory allocated before forking.
+            unsafe {
+                self.inner.pre_exec(move || {
+                    let fd = libc::open(procs.as_ptr(), libc::O_WRONLY);
+                    if fd < 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    let written = libc::write(fd, b"0".as_ptr().cast(), 1);
+                    libc::close(fd);
+                    if written != 1 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        } else if let Some(limit) = self.limits.memory {
+            // SAFETY: setrlimit is async-signal-safe.
+            unsafe {
+                self.inner.pre_exec(move || {
+                    let rlimit = libc::rlimit {
+                        rlim_cur: limit as libc::rlim_t,
+                        rlim_max: limit as libc::rlim_t,
+                    };
+                    if libc::setrlimit(libc::RLIMIT_AS, &rlimit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn apply_limits(&mut self, _cgroup: Option<&Cgroup>) -> Fallible<()> {
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn kill_process_group(pid: u32) {
+    // SAFETY: sending a signal has no memory safety implications; the
+    // negative pid targets the process group created for the child.
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_process_group(_pid: u32) {}
+
+/// Whether a line is the message printed by the Rust runtime when an
+/// allocation fails, which is how hitting `RLIMIT_AS` shows up.
+fn is_allocation_failure(line: &str) -> bool {
+    line.starts_with("memory allocation of ") && line.trim_end().ends_with(" failed")
+}
+
+fn forward_lines<R: Read + Send + 'static>(
+    reader: R,
+    stream: Stream,
+    tx: mpsc::Sender<(Stream, String)>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let reader = BufReader::new(reader);
+        for line in reader.split(b'\n') {
+            let Ok(line) = line else { break };
+            let line = String::from_utf8_lossy(&line);
+            let line = line.strip_suffix('\r').unwrap_or(&line).to_string();
+            if tx.send((stream, line)).is_err() {
+                break;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_captures_output() {
+        let logs = LogStorage::new();
+        Command::new("sh")
+            .args(["-c", "echo out; echo err >&2"])
+            .run(&logs)
+            .unwrap();
+
+        let output = String::from_utf8(logs.to_vec()).unwrap();
+        assert!(output.contains("[INFO] running `sh -c"));
+        assert!(output.contains("out\n"));
+        assert!(output.contains("err\n"));
+    }
+
+    #[test]
+    fn test_command_failure() {
+        let logs = LogStorage::new();
+        let err = Command::new("sh")
+            .args(["-c", "echo 'error: something broke'; exit 3"])
+            .run(&logs)
+            .unwrap_err();
+
+        match err.downcast_ref::<CommandError>() {
+            Some(CommandError::ExecutionFailed { status, output, .. }) => {
+                assert_eq!(status.code(), Some(3));
+                assert!(output.contains("something broke"));
+            }
+            _ => panic!("expected ExecutionFailed"),
+        }
+    }
+
+    #[test]
+    fn test_command_process_lines() {
+        let logs = LogStorage::new();
+        Command::new("sh")
+            .args(["-c", "echo keep; echo drop"])
+            .run_with(&logs, |_, line| {
+                if line == "drop" {
+                    None
+                } else {
+                    Some(line.to_uppercase())
+                }
+            })
+            .unwrap();
+
+        let output = String::from_utf8(logs.to_vec()).unwrap();
+        assert!(output.contains("KEEP"));
+        assert!(!output.contains("\ndrop\n"));
+    }
+
+    #[test]
+    fn test_command_timeout() {
+        let logs = LogStorage::new();
+        let start = Instant::now();
+        let err = Command::new("sh")
+            .args(["-c", "sleep 30 & sleep 30"])
+            .limits(Limits {
+                timeout: Some(Duration::from_millis(200)),
+                ..Limits::default()
+            })
+            .run(&logs)
+            .unwrap_err();
+
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(matches!(
+            err.downcast_ref::<CommandError>(),
+            Some(CommandError::Timeout { .. })
+        ));
+    }
+
+    #[test]
+    fn test_command_memory_rlimit() {
+        let logs = LogStorage::new();
+        let err = Command::new("sh")
+            .args([
+                "-c",
+                "echo 'memory allocation of 1048576 bytes failed' >&2; exit 134",
+            ])
+            .limits(Limits {
+                memory: Some(1024 * 1024 * 1024),
+                ..Limits::default()
+            })
+            .run(&logs)
+            .unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<CommandError>(),
+            Some(CommandError::OutOfMemory { .. })
+        ));
+    }
+
+    #[test]
+    fn test_is_allocation_failure() {
+        assert!(is_allocation_failure(
+            "memory allocation of 4096 bytes failed"
+        ));
+        assert!(!is_allocation_failure("error: out of memory"));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_command_sandbox() {
+        let dir = tempfile::tempdir().unwrap();
+        let logs = LogStorage::new();
+
+        // Skip on hosts where unprivileged user namespaces are disabled.
+        if Command::new("true")
+            .sandbox(Sandbox::new())
+            .run(&logs)
+            .is_err()
+        {
+            return;
+        }
+
+        let ro = dir.path().join("ro");
+        std::fs::create_dir(&ro).unwrap();
+        let err = Command::new("touch")
+            .arg(ro.join("file"))
+            .sandbox(Sandbox::new().read_only(&ro))
+            .run(&logs)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CommandError>(),
+            Some(CommandError::ExecutionFailed { .. })
+        ));
+        assert!(!ro.join("file").exists());
+
+        // Only the read-only paths are affected.
+        Command::new("touch")
+            .arg(dir.path().join("file"))
+            .sandbox(Sandbox::new().read_only(&ro))
+            .run(&logs)
+            .unwrap();
+        assert!(dir.path().join("file").exists());
+
+        let err = Command::new("git")
+            .args(["ls-remote", "https://github.com/rust-lang/crater"])
+            .sandbox(Sandbox::new())
+            .run(&logs)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CommandError>(),
+            Some(CommandError::NetworkAccess { .. })
+        ));
+        assert!(String::from_utf8(logs.to_vec())
+            .unwrap()
+            .contains("[ERROR] network access blocked by the sandbox"));
+    }
+}
@@ -0,0 +1,156 @@
+use crate::config::TestExecutorConfig;
+use crate::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command as StdCommand;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Outcome of running a single test binary.
+pub struct TestOutput {
+    pub success: bool,
+    /// Combined stdout and stderr of the binary.
+    pub output: String,
+}
+
+/// Runs test binaries built for a cross target somewhere they can execute,
+/// such as an OpenHarmony board reachable over hdc.
+///
+/// For every test binary the runner calls [`push`](TestExecutor::push), then
+/// [`run`](TestExecutor::run) with the handle it returned, and finally
+/// [`cleanup`](TestExecutor::cleanup), even if running failed.
+pub trait TestExecutor: Send + Sync {
+    /// Human readable description of where the tests run, used in the logs.
+    fn name(&self) -> String;
+
+    /// Make `binary` available for execution, returning a handle to it.
+    fn push(&self, binary: &Path) -> Fallible<String>;
+
+    /// Run a pushed binary and collect its output. Errors are reserved to
+    /// failures of the executor itself, not of the tests.
+    fn run(&self, handle: &str) -> Fallible<TestOutput>;
+
+    /// Remove a pushed binary.
+    fn cleanup(&self, handle: &str) -> Fallible<()>;
+}
+
+pub(super) fn from_config(config: &TestExecutorConfig) -> Box<dyn TestExecutor> {
+    match config {
+        TestExecutorConfig::Local { dir } => Box::new(LocalExecutor::new(dir.clone())),
+    }
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(unix)]
+const ETXTBSY: i32 = libc::ETXTBSY;
+#[cfg(not(unix))]
+const ETXTBSY: i32 = -1;
+
+/// Executor copying the binaries into a local directory and running them
+/// from there. It stands in for a device in CI, when the target can run on
+/// the host, e.g. through binfmt_misc and qemu-user.
+pub struct LocalExecutor {
+    dir: PathBuf,
+}
+
+impl LocalExecutor {
+    pub fn new(dir: PathBuf) -> Self {
+        LocalExecutor { dir }
+    }
+}
+
+impl TestExecutor for LocalExecutor {
+    fn name(&self) -> String {
+        format!("local directory {}", self.dir.display())
+    }
+
+    fn push(&self, binary: &Path) -> Fallible<String> {
+        let file_name = binary
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("invalid test binary {}", binary.display()))?;
+        fs::create_dir_all(&self.dir)?;
+
+        let dest = self.dir.join(format!(
+            "{}-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::SeqCst),
+            file_name.to_string_lossy()
+        ));
+        fs::copy(binary, &dest).with_context(|| format!("failed to push {}", binary.display()))?;
+        Ok(dest.to_string_lossy().into_owned())
+    }
+
+    fn run(&self, handle: &str) -> Fallible<TestOutput> {
+        // The copy may still be open for writing in a child forked by another
+        // thread at the same time, which makes exec fail with ETXTBSY.
+        let mut attempts = 0;
+        let output = loop {
+            match StdCommand::new(handle).current_dir(&self.dir).output() {
+                Err(err) if err.raw_os_error() == Some(ETXTBSY) && attempts < 10 => {
+                    attempts += 1;
+                    std::thread::sleep(std::time::Duration::from_millis(50));
+                }
+                res => break res.with_context(|| format!("failed to execute {}", handle))?,
+            }
+        };
+
+        let mut combined = String::from_utf8_lossy(&output.stdout).into_owned();
+        combined.push_str(&String::from_utf8_lossy(&output.stderr));
+        Ok(TestOutput {
+            success: output.status.success(),
+            output: combined,
+        })
+    }
+
+    fn cleanup(&self, handle: &str) -> Fallible<()> {
+        fs::remove_file(handle)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn script(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{}\n", content)).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_local_executor() {
+        let dir = tempdir().unwrap();
+        let executor = LocalExecutor::new(dir.path().join("device"));
+
+        let passing = script(dir.path(), "passing", "echo 'test result: ok'");
+        let failing = script(dir.path(), "failing", "echo 'panicked' >&2; exit 101");
+
+        let handle = executor.push(&passing).unwrap();
+        assert!(Path::new(&handle).starts_with(dir.path().join("device")));
+        let output = executor.run(&handle).unwrap();
+        assert!(output.success);
+        assert_eq!(output.output, "test result: ok\n");
+        executor.cleanup(&handle).unwrap();
+        assert!(!Path::new(&handle).exists());
+
+        let handle = executor.push(&failing).unwrap();
+        let output = executor.run(&handle).unwrap();
+        assert!(!output.success);
+        assert_eq!(output.output, "panicked\n");
+    }
+
+    #[test]
+    fn test_local_executor_missing_binary() {
+        let dir = tempdir().unwrap();
+        let executor = LocalExecutor::new(dir.path().join("device"));
+        assert!(executor.push(&dir.path().join("missing")).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+use crate::config::SandboxConfig;
+use crate::prelude::*;
+use crate::results::EncodedLog;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// In-memory buffer collecting the output of every command run for a task.
+///
+/// Once the log grows past its limits the oldest lines are dropped, since the
+/// error explaining a failure is almost always at the end.
+pub(super) struct LogStorage {
+    max_bytes: Option<usize>,
+    max_lines: Option<usize>,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    lines: VecDeque<String>,
+    bytes: usize,
+    truncated: Option<Truncated>,
+}
+
+struct Truncated {
+    limit: String,
+    lines: usize,
+    bytes: usize,
+}
+
+impl LogStorage {
+    pub(super) fn new() -> Self {
+        LogStorage {
+            max_bytes: None,
+            max_lines: None,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// Storage bounded by the `build-log-max-size` and `build-log-max-lines`
+    /// sandbox settings.
+    pub(super) fn from_config(config: &SandboxConfig) -> Self {
+        LogStorage {
+            max_bytes: Some(config.build_log_max_size.to_bytes() as usize),
+            max_lines: Some(config.build_log_max_lines as usize),
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    pub(super) fn write_line(&self, line: &str) {
+        let line = match self.max_bytes {
+            // A single line can't take more than the whole log.
+            Some(max) if line.len() + 1 > max => tail_of(line, max.saturating_sub(1)),
+            _ => line,
+        };
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.bytes += line.len() + 1;
+        inner.lines.push_back(line.to_string());
+
+        loop {
+            let limit = if self.max_lines.is_some_and(|max| inner.lines.len() > max) {
+                format!("{} lines", self.max_lines.unwrap())
+            } else if self.max_bytes.is_some_and(|max| inner.bytes > max) {
+                format!("{} bytes", self.max_bytes.unwrap())
+            } else {
+                break;
+            };
+
+            let Some(dropped) = inner.lines.pop_front() else {
+                break;
+            };
+            inner.bytes -= dropped.len() + 1;
+            let truncated = inner.truncated.get_or_insert(Truncated {
+                limit,
+                lines: 0,
+                bytes: 0,
+            });
+            truncated.lines += 1;
+            truncated.bytes += dropped.len() + 1;
+        }
+    }
+
+    pub(super) fn to_vec(&self) -> Vec<u8> {
+        let inner = self.inner.lock().unwrap();
+        let mut buffer = Vec::with_capacity(inner.bytes);
+        if let Some(truncated) = &inner.truncated {
+            buffer.extend_from_slice(
+                format!(
+                    "[INFO] log truncated after {}: the first {} lines ({} bytes) were omitted\n",
+                    truncated.limit, truncated.lines, truncated.bytes
+                )
+                .as_bytes(),
+            );
+        }
+        for line in &inner.lines {
+            buffer.extend_from_slice(line.as_bytes());
+            buffer.push(b'\n');
+        }
+        buffer
+    }
+
+    /// The log compressed for storage.
+    pub(super) fn to_encoded(&self) -> Fallible<EncodedLog> {
+        EncodedLog::compress(&self.to_vec())
+    }
+}
+
+/// The last `max` bytes of `line`, cut at a character boundary.
+fn tail_of(line: &str, max: usize) -> &str {
+    let mut start = line.len().saturating_sub(max);
+    while !line.is_char_boundary(start) {
+        start += 1;
+    }
+    &line[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limited(max_bytes: usize, max_lines: usize) -> LogStorage {
+        LogStorage {
+            max_bytes: Some(max_bytes),
+            max_lines: Some(max_lines),
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    #[test]
+    fn test_log_storage() {
+        let logs = LogStorage::new();
+        logs.write_line("first");
+        logs.write_line("second");
+        assert_eq!(logs.to_vec(), b"first\nsecond\n");
+    }
+
+    #[test]
+    fn test_log_storage_max_lines() {
+        let logs = limited(1024, 2);
+        for line in ["one", "two", "three", "four"] {
+            logs.write_line(line);
+        }
+        assert_eq!(
+            String::from_utf8(logs.to_vec()).unwrap(),
+            "[INFO] log truncated after 2 lines: the first 2 lines (8 bytes) were omitted\n\
+             three\nfour\n"
+        );
+    }
+
+    #[test]
+    fn test_log_storage_max_bytes() {
+        let logs = limited(12, 100);
+        for line in ["aaaa", "bbbb", "cccc"] {
+            logs.write_line(line);
+        }
+        assert_eq!(
+            String::from_utf8(logs.to_vec()).unwrap(),
+            "[INFO] log truncated after 12 bytes: the first 1 lines (5 bytes) were omitted\n\
+             bbbb\ncccc\n"
+        );
+
+        // Overlong lines keep their end.
+        logs.write_line("0123456789abcdef");
+        assert!(logs.to_vec().ends_with(b"\n56789abcdef\n"));
+    }
+
+    #[test]
+    fn test_tail_of_char_boundary() {
+        assert_eq!(tail_of("héllo", 4), "llo");
+        assert_eq!(tail_of("abc", 10), "abc");
+    }
+
+    #[test]
+    fn test_log_storage_to_encoded() {
+        let logs = LogStorage::new();
+        logs.write_line("error: could not compile");
+        let encoded = logs.to_encoded().unwrap();
+        assert_eq!(encoded.decode().unwrap(), "error: could not compile\n");
+    }
+}
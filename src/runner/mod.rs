@@ -1,19 +1,32 @@
 // Allow dead code for Phase 3 functions not yet fully connected
 #![allow(dead_code)]
 
+mod cgroup;
+mod command;
+mod executor;
+mod logs;
+mod ohos;
+mod prepare;
+mod sandbox;
 mod tasks;
 mod test;
+mod toolchains;
 mod worker;
+mod workspace;
 
 use crate::config::Config;
 use crate::crates::Crate;
-use crate::experiments::{Experiment, Mode};
+use crate::experiments::Experiment;
 use crate::prelude::*;
 use crate::results::TestResult;
-use crate::runner::worker::{DiskSpaceWatcher, Workspace};
+use crate::runner::worker::{DiskSpaceWatcher, Worker};
+use std::thread;
 use std::time::Duration;
 
+pub use executor::{LocalExecutor, TestExecutor, TestOutput};
+pub use prepare::prefetch;
 pub use worker::RecordProgress;
+pub use workspace::Workspace;
 
 const DISK_SPACE_WATCHER_INTERVAL: Duration = Duration::from_secs(30);
 const DISK_SPACE_WATCHER_THRESHOLD: f32 = 0.80;
@@ -24,26 +37,25 @@ pub struct OverrideResult(pub TestResult);
 
 /// Run an experiment
 ///
-/// This is the main entry point for executing an experiment. It:
-/// 1. Sets up worker threads
-/// 2. Starts disk space monitoring
-/// 3. Executes tasks for each crate with each toolchain
-/// 4. Records progress via the provided API
+/// This is the main entry point for executing an experiment. Every crate
+/// returned by `next_crate` is fetched into the workspace and tested with
+/// both toolchains of the experiment, according to its mode, and each
+/// outcome is reported through `api`.
 ///
 /// # Arguments
 ///
 /// * `ex` - The experiment to run
-/// * `workspace` - Workspace for building crates (placeholder)
+/// * `workspace` - Workspace for fetching and building crates
 /// * `api` - API for recording progress
 /// * `threads_count` - Number of worker threads to use
 /// * `config` - Configuration
 /// * `next_crate` - Function that returns the next crate to test
 pub fn run_ex(
     ex: &Experiment,
-    _workspace: &Workspace,
+    workspace: &Workspace,
     api: &dyn RecordProgress,
     threads_count: usize,
-    _config: &Config,
+    config: &Config,
     next_crate: &(dyn Fn() -> Fallible<Option<Crate>> + Send + Sync),
 ) -> Fallible<()> {
     info!(
@@ -51,77 +63,71 @@ pub fn run_ex(
         ex.name, threads_count
     );
 
+    if threads_count == 0 {
+        anyhow::bail!("at least one worker thread is required");
+    }
+
+    ohos::setup(config.ohos.as_ref(), workspace, &ex.toolchains)?;
+    toolchains::setup(config, ex)?;
+
     // Create disk space watcher
     let disk_space_watcher =
         DiskSpaceWatcher::new(DISK_SPACE_WATCHER_INTERVAL, DISK_SPACE_WATCHER_THRESHOLD, threads_count);
 
-    // In real implementation, this would:
-    // 1. Create worker threads
-    // 2. Start disk space watcher in separate thread
-    // 3. Each worker would:
-    //    - Get next crate from next_crate()
-    //    - For each toolchain in experiment:
-    //      - Create task based on experiment mode
-    //      - Execute task
-    //      - Record result via api.record_progress()
-    // 4. Wait for all workers to complete
-    // 5. Stop disk space watcher
-
-    // Placeholder implementation
-    let mut processed = 0;
-    loop {
-        match next_crate()? {
-            Some(krate) => {
-                info!("Processing crate: {}", krate);
-
-                // Process with both toolchains
-                for toolchain in &ex.toolchains {
-                    let result = match ex.mode {
-                        Mode::BuildAndTest => {
-                            info!("Running build-and-test for {} with {}", krate, toolchain);
-                            TestResult::TestPass
-                        }
-                        Mode::BuildOnly => {
-                            info!("Running build-only for {} with {}", krate, toolchain);
-                            TestResult::TestPass
-                        }
-                        Mode::CheckOnly => {
-                            info!("Running check-only for {} with {}", krate, toolchain);
-                            TestResult::TestPass
-                        }
-                        Mode::Clippy => {
-                            info!("Running clippy for {} with {}", krate, toolchain);
-                            TestResult::TestPass
-                        }
-                        Mode::Rustdoc => {
-                            info!("Running rustdoc for {} with {}", krate, toolchain);
-                            TestResult::TestPass
-                        }
-                        Mode::UnstableFeatures => {
-                            info!("Checking unstable features for {} with {}", krate, toolchain);
-                            TestResult::TestPass
-                        }
-                        Mode::Fix => {
-                            info!("Running fix for {} with {}", krate, toolchain);
-                            TestResult::TestPass
-                        }
-                    };
-
-                    // Record progress
-                    let log = b"placeholder log output";
-                    api.record_progress(ex, &krate, toolchain, log, &result, None)?;
-                }
+    let workers = (0..threads_count)
+        .map(|i| {
+            Worker::new(
+                format!("worker-{}", i),
+                workspace,
+                ex,
+                config,
+                api,
+                next_crate,
+                disk_space_watcher.clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // Workers run on dedicated threads: fetching crates relies on blocking
+    // HTTP requests, which must not happen on an async runtime thread.
+    let res = thread::scope(|scope| -> Fallible<()> {
+        let watcher_thread = thread::Builder::new()
+            .name("disk-space-watcher".into())
+            .spawn_scoped(scope, || disk_space_watcher.run(workspace))?;
 
-                processed += 1;
+        let mut handles = Vec::with_capacity(workers.len());
+        for worker in &workers {
+            let handle = thread::Builder::new()
+                .name(worker.name().into())
+                .spawn_scoped(scope, || worker.run());
+            match handle {
+                Ok(handle) => handles.push((worker.name(), handle)),
+                Err(err) => {
+                    // Let the watcher know this worker will never run.
+                    disk_space_watcher.worker_idle(true);
+                    error!("failed to spawn {}: {}", worker.name(), err);
+                }
             }
-            None => {
-                info!("No more crates to process (processed: {})", processed);
-                break;
+        }
+
+        let mut res = Ok(());
+        for (name, handle) in handles {
+            let worker_res = handle
+                .join()
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("worker {} panicked", name)));
+            if let Err(err) = worker_res {
+                error!("worker {} failed: {:#}", name, err);
+                if res.is_ok() {
+                    res = Err(err);
+                }
             }
         }
-    }
 
-    disk_space_watcher.stop();
+        disk_space_watcher.stop();
+        let _ = watcher_thread.join();
+        res
+    });
+    res?;
 
     info!("Experiment '{}' completed", ex.name);
     Ok(())
@@ -130,12 +136,25 @@ pub fn run_ex(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::crates::sources::registry::RegistryCrate;
-    use crate::experiments::{CapLints, Status};
+    use crate::experiments::{CapLints, ExperimentKind, Mode, Status};
     use crate::toolchain::{RustwideToolchain, Toolchain};
+    use crate::results::{DiagnosticCode, FailureReason};
     use chrono::Utc;
+    use std::collections::BTreeSet;
     use std::sync::Mutex;
 
+    fn local_crate(dir: &std::path::Path, name: &str, lib: &str) -> Crate {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.join("src")).unwrap();
+        std::fs::write(
+            path.join("Cargo.toml"),
+            format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n", name),
+        )
+        .unwrap();
+        std::fs::write(path.join("src/lib.rs"), lib).unwrap();
+        Crate::Path(path.to_string_lossy().into_owned())
+    }
+
     struct TestRecorder {
         results: Mutex<Vec<(String, String, TestResult)>>,
     }
@@ -146,8 +165,9 @@ mod tests {
             _ex: &Experiment,
             krate: &Crate,
             toolchain: &Toolchain,
-            _log: &[u8],
+            _log: &crate::results::EncodedLog,
             result: &TestResult,
+            _patches: &crate::results::ResolvedPatches,
             _version: Option<(&Crate, &Crate)>,
         ) -> Fallible<()> {
             let mut results = self.results.lock().unwrap();
@@ -170,8 +190,9 @@ mod tests {
 
         let ex = Experiment {
             name: "test-exp".to_string(),
+            kind: ExperimentKind::ToolchainComparison,
             toolchains: [tc.clone(), tc.clone()],
-            mode: Mode::BuildAndTest,
+            mode: Mode::CheckOnly,
             cap_lints: CapLints::Allow,
             priority: 0,
             created_at: Utc::now(),
@@ -183,16 +204,18 @@ mod tests {
             report_url: None,
             ignore_blacklist: false,
             requirement: None,
+            crate_select: None,
         };
 
-        let workspace = Workspace;
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(dir.path().join("work")).unwrap();
         let recorder = TestRecorder {
             results: Mutex::new(Vec::new()),
         };
 
         let crates = vec![
-            Crate::Registry(RegistryCrate::new("serde", "1.0.0")),
-            Crate::Registry(RegistryCrate::new("tokio", "1.0.0")),
+            local_crate(dir.path(), "passing", "pub fn answer() -> u32 { 42 }\n"),
+            local_crate(dir.path(), "failing", "pub fn answer() -> u32 { \"42\" }\n"),
         ];
         let crates = Mutex::new(crates.into_iter());
 
@@ -207,6 +230,9 @@ mod tests {
                 memory_limit: "1G".parse().unwrap(),
                 build_log_max_size: "10M".parse().unwrap(),
                 build_log_max_lines: 1000,
+                build_timeout_secs: None,
+                cgroup_root: None,
+                backend: None,
             },
             server: crate::config::ServerConfig {
                 acl: crate::config::ACL {
@@ -214,14 +240,128 @@ mod tests {
                 },
                 callback: Default::default(),
             },
+            index: None,
+            local_crates_dir: None,
             platforms: Default::default(),
+            ohos: None,
+            target_runners: Default::default(),
+            test_executors: Default::default(),
+            offline: false,
+            toolchains: Default::default(),
         };
 
         let result = run_ex(&ex, &workspace, &recorder, 2, &config, &next_crate);
         assert!(result.is_ok());
+        assert!(crates.lock().unwrap().next().is_none());
 
         let results = recorder.results.lock().unwrap();
         // 2 crates * 2 toolchains = 4 results
         assert_eq!(results.len(), 4);
+        for (krate, _, result) in results.iter() {
+            if krate.contains("passing") {
+                assert_eq!(*result, TestResult::TestPass);
+            } else {
+                let mut codes = BTreeSet::new();
+                codes.insert(DiagnosticCode::new("E0308"));
+                assert_eq!(
+                    *result,
+                    TestResult::BuildFail(FailureReason::CompilerError(codes))
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    fn test_run_ex_test_executor() {
+        let target = "x86_64-unknown-linux-gnu";
+        let mut tc: Toolchain = "stable".parse().unwrap();
+        tc.target = Some(target.to_string());
+        let ex = Experiment {
+            name: "test-exp".to_string(),
+            kind: ExperimentKind::ToolchainComparison,
+            toolchains: [tc.clone(), tc],
+            mode: Mode::BuildAndTest,
+            cap_lints: CapLints::Allow,
+            priority: 0,
+            created_at: Utc::now(),
+            started_at: None,
+            completed_at: None,
+            platform_issue: None,
+            status: Status::Queued,
+            assigned_to: None,
+            report_url: None,
+            ignore_blacklist: false,
+            requirement: None,
+            crate_select: None,
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(dir.path().join("work")).unwrap();
+        let recorder = TestRecorder {
+            results: Mutex::new(Vec::new()),
+        };
+        let mut config: Config = toml::from_str(include_str!("../../config.toml")).unwrap();
+        config.test_executors.insert(
+            target.to_string(),
+            crate::config::TestExecutorConfig::Local {
+                dir: dir.path().join("device"),
+            },
+        );
+
+        let crates = Mutex::new(
+            vec![
+                local_crate(dir.path(), "passing", "#[test]\nfn works() {}\n"),
+                local_crate(dir.path(), "failing", "#[test]\nfn fails() { panic!() }\n"),
+            ]
+            .into_iter(),
+        );
+        let next_crate = || Ok(crates.lock().unwrap().next());
+
+        run_ex(&ex, &workspace, &recorder, 1, &config, &next_crate).unwrap();
+
+        let results = recorder.results.lock().unwrap();
+        assert_eq!(results.len(), 4);
+        for (krate, _, result) in results.iter() {
+            if krate.contains("passing") {
+                assert_eq!(*result, TestResult::TestPass);
+            } else {
+                assert_eq!(*result, TestResult::TestFail(FailureReason::Unknown));
+            }
+        }
+        // Every pushed binary was cleaned up.
+        assert_eq!(std::fs::read_dir(dir.path().join("device")).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_run_ex_requires_threads() {
+        let tc: Toolchain = "stable".parse().unwrap();
+        let ex = Experiment {
+            name: "test-exp".to_string(),
+            kind: ExperimentKind::ToolchainComparison,
+            toolchains: [tc.clone(), tc],
+            mode: Mode::CheckOnly,
+            cap_lints: CapLints::Allow,
+            priority: 0,
+            created_at: Utc::now(),
+            started_at: None,
+            completed_at: None,
+            platform_issue: None,
+            status: Status::Queued,
+            assigned_to: None,
+            report_url: None,
+            ignore_blacklist: false,
+            requirement: None,
+            crate_select: None,
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(dir.path()).unwrap();
+        let recorder = TestRecorder {
+            results: Mutex::new(Vec::new()),
+        };
+        let config: Config = toml::from_str(include_str!("../../config.toml")).unwrap();
+
+        let next_crate = || Ok(None);
+        assert!(run_ex(&ex, &workspace, &recorder, 0, &config, &next_crate).is_err());
     }
 }
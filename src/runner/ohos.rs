@@ -0,0 +1,260 @@
+use crate::config::OhosConfig;
+use crate::prelude::*;
+use crate::runner::workspace::Workspace;
+use crate::toolchain::{is_ohos_target, Toolchain};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// An OpenHarmony target supported by rustc, with the matching clang target
+/// and the flags the C toolchain needs on top of it.
+struct OhosTarget {
+    triple: &'static str,
+    clang_target: &'static str,
+    cflags: &'static [&'static str],
+}
+
+const TARGETS: &[OhosTarget] = &[
+    OhosTarget {
+        triple: "aarch64-unknown-linux-ohos",
+        clang_target: "aarch64-linux-ohos",
+        cflags: &[],
+    },
+    OhosTarget {
+        triple: "armv7-unknown-linux-ohos",
+        clang_target: "arm-linux-ohos",
+        cflags: &[
+            "-march=armv7-a",
+            "-mfloat-abi=softfp",
+            "-mtune=generic-armv7-a",
+            "-mthumb",
+        ],
+    },
+    OhosTarget {
+        triple: "x86_64-unknown-linux-ohos",
+        clang_target: "x86_64-linux-ohos",
+        cflags: &[],
+    },
+];
+
+fn find_target(triple: &str) -> Fallible<&'static OhosTarget> {
+    TARGETS
+        .iter()
+        .find(|target| target.triple == triple)
+        .ok_or_else(|| anyhow::anyhow!("unsupported OpenHarmony target: {}", triple))
+}
+
+/// Make sure every OpenHarmony target of `toolchains` can be built with the
+/// configured SDK, and generate the compiler wrappers they need.
+pub(super) fn setup(
+    config: Option<&OhosConfig>,
+    workspace: &Workspace,
+    toolchains: &[Toolchain],
+) -> Fallible<()> {
+    let targets = toolchains
+        .iter()
+        .filter_map(|tc| tc.target.as_deref())
+        .filter(|target| is_ohos_target(target))
+        .map(find_target)
+        .collect::<Fallible<Vec<_>>>()?;
+    if targets.is_empty() {
+        return Ok(());
+    }
+
+    let Some(config) = config else {
+        anyhow::bail!(
+            "building for {} requires the [ohos] section in the configuration",
+            targets[0].triple
+        );
+    };
+    if !config.clang().is_file() {
+        anyhow::bail!(
+            "OpenHarmony SDK not found: {} does not exist",
+            config.clang().display()
+        );
+    }
+
+    for target in targets {
+        for (compiler, path) in [("clang", config.clang()), ("clang++", config.clangxx())] {
+            write_wrapper(
+                &wrapper_path(workspace, target, compiler),
+                &wrapper_script(config, target, &path),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Environment variables pointing cargo and cc-rs at the SDK when building
+/// for `triple`, or nothing for other targets.
+pub(super) fn cargo_env(
+    config: Option<&OhosConfig>,
+    workspace: &Workspace,
+    triple: &str,
+) -> Vec<(String, PathBuf)> {
+    let (Some(config), Ok(target)) = (config, find_target(triple)) else {
+        return Vec::new();
+    };
+
+    let cargo_name = triple.to_uppercase().replace('-', "_");
+    let cc_name = triple.replace('-', "_");
+    let clang = wrapper_path(workspace, target, "clang");
+    let clangxx = wrapper_path(workspace, target, "clang++");
+    vec![
+        (format!("CARGO_TARGET_{}_LINKER", cargo_name), clang.clone()),
+        (format!("CARGO_TARGET_{}_AR", cargo_name), config.llvm_ar()),
+        (format!("CC_{}", cc_name), clang),
+        (format!("CXX_{}", cc_name), clangxx),
+        (format!("AR_{}", cc_name), config.llvm_ar()),
+    ]
+}
+
+fn wrapper_path(workspace: &Workspace, target: &OhosTarget, compiler: &str) -> PathBuf {
+    workspace
+        .tools_dir()
+        .join("ohos")
+        .join(format!("{}-{}", target.triple, compiler))
+}
+
+fn wrapper_script(config: &OhosConfig, target: &OhosTarget, compiler: &Path) -> String {
+    let mut args = vec![
+        shell_quote(&compiler.to_string_lossy()),
+        "-target".to_string(),
+        target.clang_target.to_string(),
+        shell_quote(&format!("--sysroot={}", config.sysroot().display())),
+        "-D__MUSL__".to_string(),
+    ];
+    args.extend(target.cflags.iter().map(|flag| flag.to_string()));
+    format!("#!/bin/sh\nexec {} \"$@\"\n", args.join(" "))
+}
+
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Write an executable script, replacing any previous version atomically so
+/// that concurrent builds never see a partial file.
+fn write_wrapper(path: &Path, script: &str) -> Fallible<()> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("invalid wrapper path {}", path.display()))?;
+    fs::create_dir_all(dir)?;
+
+    let tmp = tempfile::NamedTempFile::new_in(dir)?;
+    fs::write(tmp.path(), script)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(tmp.path(), fs::Permissions::from_mode(0o755))?;
+    }
+    tmp.persist(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn ohos_toolchain(target: &str) -> Toolchain {
+        let mut tc: Toolchain = "stable".parse().unwrap();
+        tc.target = Some(target.to_string());
+        tc
+    }
+
+    fn fake_sdk(dir: &Path) -> OhosConfig {
+        let config = OhosConfig {
+            sdk_path: dir.join("ohos-sdk"),
+        };
+        fs::create_dir_all(config.clang().parent().unwrap()).unwrap();
+        fs::write(config.clang(), "").unwrap();
+        config
+    }
+
+    #[test]
+    fn test_setup_requires_sdk() {
+        let dir = tempdir().unwrap();
+        let workspace = Workspace::new(dir.path().join("work")).unwrap();
+        let toolchains = [ohos_toolchain("aarch64-unknown-linux-ohos")];
+
+        // Toolchains without an OpenHarmony target don't need the SDK.
+        setup(None, &workspace, &["stable".parse().unwrap()]).unwrap();
+
+        assert!(setup(None, &workspace, &toolchains).is_err());
+        let missing = OhosConfig {
+            sdk_path: dir.path().join("missing"),
+        };
+        assert!(setup(Some(&missing), &workspace, &toolchains).is_err());
+        assert!(setup(
+            Some(&fake_sdk(dir.path())),
+            &workspace,
+            &[ohos_toolchain("riscv64-unknown-linux-ohos")]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_setup_writes_wrappers() {
+        let dir = tempdir().unwrap();
+        let workspace = Workspace::new(dir.path().join("work")).unwrap();
+        let config = fake_sdk(dir.path());
+        let toolchains = [ohos_toolchain("armv7-unknown-linux-ohos")];
+        setup(Some(&config), &workspace, &toolchains).unwrap();
+
+        let target = find_target("armv7-unknown-linux-ohos").unwrap();
+        let wrapper = wrapper_path(&workspace, target, "clang");
+        let script = fs::read_to_string(&wrapper).unwrap();
+        assert!(script.starts_with("#!/bin/sh\nexec '"));
+        assert!(script.contains("-target arm-linux-ohos"));
+        assert!(script.contains(&format!("'--sysroot={}'", config.sysroot().display())));
+        assert!(script.contains("-mfloat-abi=softfp"));
+        assert!(wrapper_path(&workspace, target, "clang++").is_file());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&wrapper).unwrap().permissions().mode();
+            assert_eq!(mode & 0o111, 0o111);
+        }
+    }
+
+    #[test]
+    fn test_cargo_env() {
+        let dir = tempdir().unwrap();
+        let workspace = Workspace::new(dir.path().join("work")).unwrap();
+        let config = OhosConfig {
+            sdk_path: PathBuf::from("/opt/ohos-sdk/linux"),
+        };
+
+        assert!(cargo_env(Some(&config), &workspace, "x86_64-unknown-linux-gnu").is_empty());
+        assert!(cargo_env(None, &workspace, "aarch64-unknown-linux-ohos").is_empty());
+
+        let env = cargo_env(Some(&config), &workspace, "aarch64-unknown-linux-ohos");
+        let get = |key: &str| env.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+        assert_eq!(
+            get("CARGO_TARGET_AARCH64_UNKNOWN_LINUX_OHOS_LINKER"),
+            Some(
+                workspace
+                    .tools_dir()
+                    .join("ohos/aarch64-unknown-linux-ohos-clang")
+            )
+        );
+        assert_eq!(
+            get("CXX_aarch64_unknown_linux_ohos"),
+            Some(
+                workspace
+                    .tools_dir()
+                    .join("ohos/aarch64-unknown-linux-ohos-clang++")
+            )
+        );
+        assert_eq!(
+            get("AR_aarch64_unknown_linux_ohos"),
+            Some(PathBuf::from("/opt/ohos-sdk/linux/native/llvm/bin/llvm-ar"))
+        );
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/opt/sdk"), "'/opt/sdk'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+}
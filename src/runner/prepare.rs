@@ -0,0 +1,577 @@
+use crate::config::Config;
+use crate::crates::index::{self, Index};
+use crate::crates::lists::DEFAULT_LOCAL_CRATES_DIR;
+use crate::crates::{Crate, RegistryCrate};
+use crate::prelude::*;
+use crate::results::{BrokenReason, ResolvedPatches, TestResult};
+use crate::runner::command::Command;
+use crate::runner::logs::LogStorage;
+use crate::runner::tasks::TaskCtx;
+use crate::runner::workspace::Workspace;
+use crate::runner::OverrideResult;
+use crate::toolchain::CratePatch;
+use crate::utils::fs::{copy_dir, remove_dir_all};
+use crate::utils::http::HttpClient;
+use flate2::read::GzDecoder;
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// The `dl` setting of crates.io, used when no index is configured.
+const CRATES_IO_DL: &str = "https://static.crates.io/crates";
+const DOWNLOAD_TIMEOUT_SECS: u64 = 300;
+
+lazy_static! {
+    /// Locks serializing the git commands run on each mirror by the workers.
+    static ref MIRROR_LOCKS: Mutex<HashMap<PathBuf, Arc<Mutex<()>>>> = Mutex::new(HashMap::new());
+}
+
+/// Where the source of a crate is copied from into a build directory.
+#[derive(Debug, PartialEq, Eq)]
+enum Source {
+    /// A directory shared by every worker, copied as is.
+    Dir(PathBuf),
+    /// A bare git mirror, cloned by each worker into its own build directory.
+    Mirror(PathBuf),
+}
+
+/// Prepare the build directory of `ctx` for a build: copy a pristine copy of
+/// the crate source into it, then generate a lockfile if the crate does not
+/// ship one and download its dependencies, so that the actual build can run
+/// with `--frozen`, and without network access when sandboxed.
+pub(super) fn prepare(ctx: &TaskCtx) -> Fallible<()> {
+    let source = fetch(ctx.workspace, ctx.config, ctx.krate, ctx.logs)?;
+
+    let dest = ctx.build_dir.source_dir();
+    remove_dir_all(&dest)?;
+    match source {
+        Source::Dir(path) => copy_dir(&path, &dest)?,
+        Source::Mirror(mirror) => with_mirror_lock(&mirror, || {
+            Command::new("git")
+                .args(["clone", "--quiet"])
+                .arg(&mirror)
+                .arg(&dest)
+                .run(ctx.logs)
+                .with_context(|| format!("failed to clone {}", ctx.krate))
+        })?,
+    }
+
+    if let Some(sha) = ctx.krate.sha() {
+        Command::new("git")
+            .args(["checkout", "--quiet", "--detach", sha])
+            .cwd(&dest)
+            .run(ctx.logs)?;
+    }
+
+    let crate_dir = ctx.crate_dir();
+    if !crate_dir.join("Cargo.toml").is_file() {
+        anyhow::bail!("missing Cargo.toml in {}", ctx.krate);
+    }
+
+    // Offline, dependencies can only come from cargo's own cache.
+    let offline: &[&str] = if ctx.config.offline { &["--offline"] } else { &[] };
+
+    // Crates in a subdirectory may share the lockfile of their workspace.
+    if !crate_dir.join("Cargo.lock").is_file() && !dest.join("Cargo.lock").is_file() {
+        ctx.cargo_with_network()
+            .arg("generate-lockfile")
+            .args(offline)
+            .run(ctx.logs)?;
+    }
+
+    // Patched dependencies aren't in the lockfile shipped by the crate, so
+    // cargo must be allowed to add them.
+    let locked: &[&str] = if ctx.toolchain.patches.is_empty() { &["--locked"] } else { &[] };
+    ctx.cargo_with_network()
+        .arg("fetch")
+        .args(locked)
+        .args(offline)
+        .args(ctx.target_args())
+        .run(ctx.logs)?;
+
+    Ok(())
+}
+
+/// Commits the crates patched by the task's toolchain resolved to, read from
+/// the lockfile of the prepared crate.
+pub(super) fn resolved_patches(ctx: &TaskCtx) -> Fallible<ResolvedPatches> {
+    if ctx.toolchain.patches.is_empty() {
+        return Ok(ResolvedPatches::new());
+    }
+    let lockfile = [ctx.crate_dir(), ctx.build_dir.source_dir()]
+        .into_iter()
+        .map(|dir| dir.join("Cargo.lock"))
+        .find(|path| path.is_file());
+    match lockfile {
+        Some(path) => patch_commits(&fs::read_to_string(path)?, &ctx.toolchain.patches),
+        // The crate failed before its dependencies were resolved.
+        None => Ok(ResolvedPatches::new()),
+    }
+}
+
+/// Commits of the git sources of `patches` in the lockfile `lock`.
+fn patch_commits(lock: &str, patches: &[CratePatch]) -> Fallible<ResolvedPatches> {
+    let lock: toml::Value = toml::from_str(lock)?;
+    let packages = lock
+        .get("package")
+        .and_then(|packages| packages.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    let mut resolved = ResolvedPatches::new();
+    for patch in patches {
+        // Sources are written `git+<repo>?branch=<branch>#<sha>`.
+        let sha = packages
+            .iter()
+            .filter(|package| package.get("name").and_then(|n| n.as_str()) == Some(&patch.name))
+            .filter_map(|package| package.get("source")?.as_str()?.strip_prefix("git+"))
+            .filter(|source| source.split(['?', '#']).next() == Some(patch.repo.as_str()))
+            .find_map(|source| source.rsplit_once('#').map(|(_, sha)| sha));
+        if let Some(sha) = sha {
+            resolved.insert(patch.name.clone(), sha.to_string());
+        }
+    }
+    Ok(resolved)
+}
+
+/// Download the source of `krate` into the workspace cache without building
+/// it, so that later runs can use it in offline mode.
+///
+/// Only the crate itself is cached: its dependencies must already be in
+/// cargo's own cache, from an earlier online run, to build it offline.
+pub fn prefetch(workspace: &Workspace, config: &Config, krate: &Crate) -> Fallible<()> {
+    let config = Config {
+        offline: false,
+        ..config.clone()
+    };
+    fetch(workspace, &config, krate, &LogStorage::new())?;
+    Ok(())
+}
+
+/// Make the source of `krate` available in the workspace and return where
+/// to copy it from.
+///
+/// Downloaded sources are cached in the workspace and shared by every worker:
+/// git repositories are kept as bare mirrors, which each worker clones into
+/// its own build directory, so no worker reads a checkout another one updates.
+/// In offline mode a source missing from the cache makes the crate broken.
+fn fetch(
+    workspace: &Workspace,
+    config: &Config,
+    krate: &Crate,
+    logs: &LogStorage,
+) -> Fallible<Source> {
+    match krate {
+        Crate::Registry(krate) => {
+            let dest = workspace
+                .sources_dir()
+                .join("reg")
+                .join(&*krate.name)
+                .join(&*krate.version);
+            if !dest.is_dir() {
+                let bytes = cached_crate(workspace, config, krate, logs)?;
+                unpack_crate(&bytes, &dest)?;
+            }
+            Ok(Source::Dir(dest))
+        }
+        Crate::GitHub(repo) => git_mirror(workspace, config, &repo.url(), logs).map(Source::Mirror),
+        Crate::GitCode(repo) => git_mirror(workspace, config, &repo.url(), logs).map(Source::Mirror),
+        Crate::Gitee(repo) => git_mirror(workspace, config, &repo.url(), logs).map(Source::Mirror),
+        Crate::Git(repo) => git_mirror(workspace, config, &repo.url, logs).map(Source::Mirror),
+        Crate::Local(name) => {
+            let dir = config
+                .local_crates_dir
+                .as_deref()
+                .unwrap_or(Path::new(DEFAULT_LOCAL_CRATES_DIR));
+            Ok(Source::Dir(dir.join(name)))
+        }
+        Crate::Path(path) => Ok(Source::Dir(PathBuf::from(path))),
+    }
+}
+
+/// Error returned in offline mode when the source of a crate isn't cached.
+fn missing_source(what: &str, logs: &LogStorage) -> Error {
+    logs.write_line(&format!("[ERROR] {} is not cached and crater is offline", what));
+    OverrideResult(TestResult::BrokenCrate(BrokenReason::MissingSource)).into()
+}
+
+/// The `.crate` archive of `krate`, downloaded into the cache if needed.
+///
+/// When an index is configured the archive is downloaded from the `dl` of its
+/// `config.json` and checked against the checksum it records; a corrupted
+/// cached copy is discarded and downloaded again.
+fn cached_crate(
+    workspace: &Workspace,
+    config: &Config,
+    krate: &RegistryCrate,
+    logs: &LogStorage,
+) -> Fallible<Vec<u8>> {
+    let path = workspace
+        .cache_dir()
+        .join("crates")
+        .join(&*krate.name)
+        .join(format!("{}-{}.crate", krate.name, krate.version));
+    let (dl, cksum) = match &config.index {
+        Some(index) => {
+            let index = Index::new(&index.path);
+            let cksum = index
+                .krate(&krate.name)?
+                .and_then(|c| c.versions.into_iter().find(|v| v.num == *krate.version))
+                .and_then(|v| v.cksum);
+            (index.dl()?, cksum)
+        }
+        None => (None, None),
+    };
+
+    if path.is_file() {
+        let bytes = fs::read(&path)?;
+        match verify_checksum(&bytes, cksum.as_deref()) {
+            Ok(()) => return Ok(bytes),
+            Err(err) => {
+                logs.write_line(&format!("[WARN] discarding cached {}: {}", krate, err));
+                fs::remove_file(&path)?;
+            }
+        }
+    }
+    if config.offline {
+        return Err(missing_source(&krate.to_string(), logs));
+    }
+
+    let url = index::download_url(
+        dl.as_deref().unwrap_or(CRATES_IO_DL),
+        &krate.name,
+        &krate.version,
+        cksum.as_deref(),
+    );
+    logs.write_line(&format!("[INFO] fetching {}", url));
+    let bytes = HttpClient::new(DOWNLOAD_TIMEOUT_SECS)?
+        .get_bytes(&url)
+        .with_context(|| format!("failed to download {}", krate))?;
+    verify_checksum(&bytes, cksum.as_deref())
+        .with_context(|| format!("failed to download {}", krate))?;
+
+    let tmp = tempdir_next_to(&path)?;
+    fs::write(tmp.path().join("download"), &bytes)?;
+    fs::rename(tmp.path().join("download"), &path)?;
+    Ok(bytes)
+}
+
+fn verify_checksum(bytes: &[u8], expected: Option<&str>) -> Fallible<()> {
+    // Some index entries record an empty checksum: nothing to verify then.
+    if let Some(expected) = expected.filter(|expected| !expected.is_empty()) {
+        let actual = crate::utils::hex::encode(Sha256::digest(bytes));
+        if !actual.eq_ignore_ascii_case(expected) {
+            anyhow::bail!("checksum mismatch: expected {}, got {}", expected, actual);
+        }
+    }
+    Ok(())
+}
+
+/// Bare mirror of `url` in the cache, created or updated unless offline.
+fn git_mirror(
+    workspace: &Workspace,
+    config: &Config,
+    url: &str,
+    logs: &LogStorage,
+) -> Fallible<PathBuf> {
+    let mirror = workspace
+        .cache_dir()
+        .join("git")
+        .join(format!("{}.git", crate::utils::hex::encode(url.as_bytes())));
+    if config.offline {
+        if !mirror.is_dir() {
+            return Err(missing_source(url, logs));
+        }
+        return Ok(mirror);
+    }
+
+    with_mirror_lock(&mirror, || {
+        if mirror.is_dir() {
+            return Command::new("git")
+                .args(["remote", "update", "--prune"])
+                .cwd(&mirror)
+                .env("GIT_TERMINAL_PROMPT", "0")
+                .run(logs)
+                .with_context(|| format!("failed to update mirror of {}", url));
+        }
+
+        let tmp = tempdir_next_to(&mirror)?;
+        let checkout = tmp.path().join("mirror.git");
+        Command::new("git")
+            .args(["clone", "--quiet", "--mirror", url])
+            .arg(&checkout)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .run(logs)
+            .with_context(|| format!("failed to clone git repository {}", url))?;
+        persist(&checkout, &mirror)
+    })?;
+    Ok(mirror)
+}
+
+/// Run `f` while holding the lock of `mirror`, so that a worker never clones
+/// a mirror another worker is updating.
+fn with_mirror_lock<T>(mirror: &Path, f: impl FnOnce() -> Fallible<T>) -> Fallible<T> {
+    let lock = MIRROR_LOCKS
+        .lock()
+        .unwrap()
+        .entry(mirror.to_path_buf())
+        .or_default()
+        .clone();
+    let _guard = lock.lock().unwrap();
+    f()
+}
+
+/// Unpack a `.crate` tarball into `dest`, stripping the `<name>-<version>/`
+/// directory every crate archive is wrapped in.
+fn unpack_crate(bytes: &[u8], dest: &Path) -> Fallible<()> {
+    let tmp = tempdir_next_to(dest)?;
+    tar::Archive::new(GzDecoder::new(bytes)).unpack(tmp.path())?;
+
+    let mut entries = fs::read_dir(tmp.path())?.collect::<Result<Vec<_>, _>>()?;
+    if entries.len() != 1 || !entries[0].file_type()?.is_dir() {
+        anyhow::bail!("unexpected layout of crate archive for {}", dest.display());
+    }
+    persist(&entries.remove(0).path(), dest)
+}
+
+fn tempdir_next_to(dest: &Path) -> Fallible<tempfile::TempDir> {
+    let parent = dest
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("invalid source path {}", dest.display()))?;
+    fs::create_dir_all(parent)?;
+    Ok(tempfile::Builder::new().prefix(".tmp").tempdir_in(parent)?)
+}
+
+/// Atomically move a freshly fetched source into its final location. Another
+/// worker may have won the race, in which case its copy is kept.
+fn persist(src: &Path, dest: &Path) -> Fallible<()> {
+    match fs::rename(src, dest) {
+        Ok(()) => Ok(()),
+        Err(_) if dest.is_dir() => Ok(()),
+        Err(err) => Err(Error::from(err)
+            .context(format!("failed to move {} to {}", src.display(), dest.display()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use tempfile::tempdir;
+
+    fn test_config() -> Config {
+        toml::from_str(include_str!("../../config.toml")).unwrap()
+    }
+
+    fn is_missing_source(err: &Error) -> bool {
+        matches!(
+            err.downcast_ref::<OverrideResult>(),
+            Some(OverrideResult(TestResult::BrokenCrate(BrokenReason::MissingSource)))
+        )
+    }
+
+    fn crate_archive(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_unpack_crate_strips_prefix() {
+        let archive = crate_archive(&[
+            ("demo-0.1.0/Cargo.toml", "[package]\nname = \"demo\"\n"),
+            ("demo-0.1.0/src/lib.rs", "pub fn demo() {}\n"),
+        ]);
+
+        let dir = tempdir().unwrap();
+        let dest = dir.path().join("reg/demo/0.1.0");
+        unpack_crate(&archive, &dest).unwrap();
+
+        assert!(dest.join("Cargo.toml").is_file());
+        assert!(dest.join("src/lib.rs").is_file());
+    }
+
+    #[test]
+    fn test_patch_commits() {
+        let lock = r#"
+version = 3
+
+[[package]]
+name = "demo"
+version = "0.1.0"
+dependencies = ["libc"]
+
+[[package]]
+name = "libc"
+version = "0.2.150"
+source = "git+https://gitee.com/openharmony/libc?branch=ohos#0123456789abcdef"
+
+[[package]]
+name = "nix"
+version = "0.27.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "rand"
+version = "0.8.5"
+source = "git+https://gitee.com/openharmony/rand-ohos#fedcba9876543210"
+"#;
+        let patches = [
+            "libc=https://gitee.com/openharmony/libc#ohos",
+            "nix=https://gitee.com/openharmony/nix#ohos",
+            "rand=https://gitee.com/openharmony/rand#ohos",
+        ]
+        .iter()
+        .map(|spec| spec.parse().unwrap())
+        .collect::<Vec<CratePatch>>();
+
+        // nix stays on crates.io and rand comes from another repository
+        // sharing the prefix of its patch, so neither patch is used.
+        let resolved = patch_commits(lock, &patches).unwrap();
+        assert_eq!(
+            resolved.into_iter().collect::<Vec<_>>(),
+            vec![("libc".to_string(), "0123456789abcdef".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_fetch_path_crate() {
+        let dir = tempdir().unwrap();
+        let workspace = Workspace::new(dir.path().join("work")).unwrap();
+        let logs = LogStorage::new();
+
+        let krate = Crate::Path("/some/crate".to_string());
+        let source = fetch(&workspace, &test_config(), &krate, &logs).unwrap();
+        assert_eq!(source, Source::Dir(PathBuf::from("/some/crate")));
+    }
+
+    #[test]
+    fn test_fetch_local_crate() {
+        let dir = tempdir().unwrap();
+        let workspace = Workspace::new(dir.path().join("work")).unwrap();
+        let logs = LogStorage::new();
+        let krate = Crate::Local("my-crate".to_string());
+
+        let mut config = test_config();
+        let source = fetch(&workspace, &config, &krate, &logs).unwrap();
+        assert_eq!(source, Source::Dir(PathBuf::from("local-crates/my-crate")));
+
+        config.local_crates_dir = Some(PathBuf::from("/srv/ohos-crates"));
+        let source = fetch(&workspace, &config, &krate, &logs).unwrap();
+        assert_eq!(source, Source::Dir(PathBuf::from("/srv/ohos-crates/my-crate")));
+    }
+
+    #[test]
+    fn test_fetch_git_crate() {
+        let dir = tempdir().unwrap();
+        let logs = LogStorage::new();
+
+        let upstream = dir.path().join("upstream");
+        fs::create_dir_all(&upstream).unwrap();
+        fs::write(upstream.join("Cargo.toml"), "[package]\n").unwrap();
+        for args in [
+            &["init", "--quiet"][..],
+            &["add", "Cargo.toml"],
+            &["-c", "user.name=test", "-c", "user.email=test@example.com", "commit", "--quiet", "-m", "init"],
+        ] {
+            Command::new("git").args(args).cwd(&upstream).run(&logs).unwrap();
+        }
+
+        let workspace = Workspace::new(dir.path().join("work")).unwrap();
+        let mut config = test_config();
+        let krate = Crate::Git(crate::crates::GitRepo::new(upstream.to_str().unwrap()));
+
+        config.offline = true;
+        let err = fetch(&workspace, &config, &krate, &logs).unwrap_err();
+        assert!(is_missing_source(&err));
+
+        config.offline = false;
+        let source = fetch(&workspace, &config, &krate, &logs).unwrap();
+        let mirror = match &source {
+            Source::Mirror(mirror) => mirror.clone(),
+            other => panic!("expected a git mirror, got {:?}", other),
+        };
+        assert!(mirror.starts_with(workspace.cache_dir().join("git")));
+        assert_eq!(fs::read_dir(workspace.cache_dir().join("git")).unwrap().count(), 1);
+
+        // A second fetch updates the existing mirror in place.
+        assert_eq!(fetch(&workspace, &config, &krate, &logs).unwrap(), source);
+
+        // Offline, sources are cloned from the mirror alone.
+        config.offline = true;
+        fs::remove_dir_all(&upstream).unwrap();
+        assert_eq!(fetch(&workspace, &config, &krate, &logs).unwrap(), source);
+        let checkout = dir.path().join("checkout");
+        Command::new("git")
+            .args(["clone", "--quiet"])
+            .arg(&mirror)
+            .arg(&checkout)
+            .run(&logs)
+            .unwrap();
+        assert!(checkout.join("Cargo.toml").is_file());
+    }
+
+    #[test]
+    fn test_fetch_cached_crate_offline() {
+        let dir = tempdir().unwrap();
+        let workspace = Workspace::new(dir.path().join("work")).unwrap();
+        let logs = LogStorage::new();
+
+        let archive = crate_archive(&[("demo-0.1.0/Cargo.toml", "[package]\nname = \"demo\"\n")]);
+        let index = dir.path().join("index");
+        fs::create_dir_all(index.join("de/mo")).unwrap();
+        fs::write(
+            index.join("de/mo/demo"),
+            format!(
+                "{{\"name\":\"demo\",\"vers\":\"0.1.0\",\"cksum\":\"{}\"}}\n",
+                crate::utils::hex::encode(Sha256::digest(&archive))
+            ),
+        )
+        .unwrap();
+
+        let mut config = test_config();
+        config.offline = true;
+        config.index = Some(crate::config::IndexConfig {
+            path: index,
+            downloads: None,
+        });
+        let krate = Crate::Registry(RegistryCrate::new("demo", "0.1.0"));
+
+        let err = fetch(&workspace, &config, &krate, &logs).unwrap_err();
+        assert!(is_missing_source(&err));
+
+        // A corrupted download is discarded instead of being unpacked.
+        let cached = workspace.cache_dir().join("crates/demo/demo-0.1.0.crate");
+        fs::create_dir_all(cached.parent().unwrap()).unwrap();
+        fs::write(&cached, b"garbage").unwrap();
+        let err = fetch(&workspace, &config, &krate, &logs).unwrap_err();
+        assert!(is_missing_source(&err));
+        assert!(!cached.exists());
+
+        fs::write(&cached, &archive).unwrap();
+        match fetch(&workspace, &config, &krate, &logs).unwrap() {
+            Source::Dir(path) => assert!(path.join("Cargo.toml").is_file()),
+            other => panic!("expected a directory, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let cksum = crate::utils::hex::encode(Sha256::digest(b"data"));
+        verify_checksum(b"data", Some(&cksum)).unwrap();
+        assert!(verify_checksum(b"other", Some(&cksum)).is_err());
+        // An unknown checksum isn't a mismatch.
+        verify_checksum(b"other", None).unwrap();
+        verify_checksum(b"other", Some("")).unwrap();
+    }
+}
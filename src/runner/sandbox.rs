@@ -0,0 +1,179 @@
+use crate::prelude::*;
+use std::path::PathBuf;
+use std::process::Command as StdCommand;
+
+/// Output of common networking stacks (std, curl, git, hyper) when the
+/// network is unreachable, which inside the sandbox means the build tried to
+/// access it.
+const NETWORK_ERRORS: &[&str] = &[
+    "Network is unreachable",
+    "network unreachable",
+    "failed to lookup address information",
+    "Temporary failure in name resolution",
+    "Name or service not known",
+    "Could not resolve host",
+    "dns error",
+];
+
+/// Isolation applied to a command on top of its resource limits: the command
+/// runs in fresh user, mount and network namespaces, so it only sees the
+/// loopback interface, and the `read_only` paths are remounted read-only.
+#[derive(Clone, Default)]
+pub(super) struct Sandbox {
+    read_only: Vec<PathBuf>,
+}
+
+impl Sandbox {
+    pub(super) fn new() -> Self {
+        Sandbox::default()
+    }
+
+    pub(super) fn read_only<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.read_only.push(path.into());
+        self
+    }
+
+    #[cfg(target_os = "linux")]
+    pub(super) fn apply(&self, cmd: &mut StdCommand) -> Fallible<()> {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::process::CommandExt;
+
+        // Everything the child needs is allocated before forking.
+        let uid_map = format!("{0} {0} 1", unsafe { libc::getuid() });
+        let gid_map = format!("{0} {0} 1", unsafe { libc::getgid() });
+        let read_only = self
+            .read_only
+            .iter()
+            .map(|path| {
+                let path = path
+                    .canonicalize()
+                    .with_context(|| format!("missing sandbox path {}", path.display()))?;
+                Ok(CString::new(path.as_os_str().as_bytes())?)
+            })
+            .collect::<Fallible<Vec<_>>>()?;
+
+        // SAFETY: the closure only performs async-signal-safe syscalls on
+        // memory allocated before forking.
+        unsafe {
+            cmd.pre_exec(move || {
+                check(libc::unshare(
+                    libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWNET,
+                ))?;
+                write_file(c"/proc/self/setgroups", b"deny")?;
+                write_file(c"/proc/self/uid_map", uid_map.as_bytes())?;
+                write_file(c"/proc/self/gid_map", gid_map.as_bytes())?;
+
+                // Keep the mounts below from propagating to the host.
+                check(libc::mount(
+                    std::ptr::null(),
+                    c"/".as_ptr(),
+                    std::ptr::null(),
+                    libc::MS_REC | libc::MS_PRIVATE,
+                    std::ptr::null(),
+                ))?;
+                for path in &read_only {
+                    remount_read_only(path)?;
+                }
+
+                // Tests commonly bind to localhost, which must keep working.
+                loopback_up();
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(super) fn apply(&self, _cmd: &mut StdCommand) -> Fallible<()> {
+        anyhow::bail!("the namespace sandbox is only supported on Linux");
+    }
+}
+
+/// Whether an output line shows that the command tried to reach the network.
+pub(super) fn is_network_access(line: &str) -> bool {
+    NETWORK_ERRORS.iter().any(|error| line.contains(error))
+}
+
+#[cfg(target_os = "linux")]
+fn check(res: libc::c_int) -> std::io::Result<()> {
+    if res < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn write_file(path: &std::ffi::CStr, content: &[u8]) -> std::io::Result<()> {
+    let fd = libc::open(path.as_ptr(), libc::O_WRONLY);
+    check(fd)?;
+    let written = libc::write(fd, content.as_ptr().cast(), content.len());
+    libc::close(fd);
+    if written != content.len() as isize {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn remount_read_only(path: &std::ffi::CStr) -> std::io::Result<()> {
+    check(libc::mount(
+        path.as_ptr(),
+        path.as_ptr(),
+        std::ptr::null(),
+        libc::MS_BIND | libc::MS_REC,
+        std::ptr::null(),
+    ))?;
+
+    // Flags locked by the parent namespace must be kept on remount.
+    let mut stat: libc::statvfs = std::mem::zeroed();
+    check(libc::statvfs(path.as_ptr(), &mut stat))?;
+    let locked = libc::MS_NOSUID
+        | libc::MS_NODEV
+        | libc::MS_NOEXEC
+        | libc::MS_NOATIME
+        | libc::MS_NODIRATIME
+        | libc::MS_RELATIME;
+    check(libc::mount(
+        std::ptr::null(),
+        path.as_ptr(),
+        std::ptr::null(),
+        libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY | (stat.f_flag & locked),
+        std::ptr::null(),
+    ))
+}
+
+/// Bring up the loopback interface of the new network namespace. Failures
+/// are ignored: the sandbox stays closed either way.
+#[cfg(target_os = "linux")]
+unsafe fn loopback_up() {
+    let sock = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+    if sock < 0 {
+        return;
+    }
+    let mut req: libc::ifreq = std::mem::zeroed();
+    for (dst, src) in req.ifr_name.iter_mut().zip(b"lo") {
+        *dst = *src as libc::c_char;
+    }
+    req.ifr_ifru.ifru_flags =
+        (libc::IFF_UP | libc::IFF_LOOPBACK | libc::IFF_RUNNING) as libc::c_short;
+    libc::ioctl(sock, libc::SIOCSIFFLAGS as _, &req);
+    libc::close(sock);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_network_access() {
+        assert!(is_network_access(
+            "Error: Os { code: 101, kind: NetworkUnreachable, message: \"Network is unreachable\" }"
+        ));
+        assert!(is_network_access(
+            "curl: (6) Could not resolve host: example.com"
+        ));
+        assert!(!is_network_access("error[E0308]: mismatched types"));
+    }
+}
@@ -1,21 +1,184 @@
-use crate::config::Config;
+use crate::config::{Config, SandboxBackend};
 use crate::crates::Crate;
-use crate::experiments::Experiment;
-use crate::toolchain::Toolchain;
+use crate::experiments::{Experiment, Mode};
+use crate::prelude::*;
+use crate::results::{ResolvedPatches, TestResult};
+use crate::runner::command::{Command, Limits};
+use crate::runner::executor::{self, TestExecutor};
+use crate::runner::logs::LogStorage;
+use crate::runner::ohos;
+use crate::runner::prepare;
+use crate::runner::sandbox::Sandbox;
+use crate::runner::test;
+use crate::runner::workspace::{BuildDirectory, Workspace};
+use crate::runner::{OverrideResult, RecordProgress};
+use crate::toolchain::{is_ohos_target, CratePatch, Toolchain};
 use std::fmt;
-
-// Placeholder for rustwide types - in real implementation, these would come from rustwide crate
-pub struct BuildDirectory;
-pub struct Build;
+use std::path::PathBuf;
 
 pub(super) struct TaskCtx<'ctx> {
     pub(super) config: &'ctx Config,
+    pub(super) workspace: &'ctx Workspace,
+    pub(super) build_dir: &'ctx BuildDirectory,
+    pub(super) logs: &'ctx LogStorage,
     pub(super) experiment: &'ctx Experiment,
     pub(super) toolchain: &'ctx Toolchain,
     pub(super) krate: &'ctx Crate,
     pub(super) quiet: bool,
 }
 
+impl<'ctx> TaskCtx<'ctx> {
+    /// Directory of the crate being tested inside the build directory: the
+    /// root of its source, or the subdirectory it lives in.
+    pub(super) fn crate_dir(&self) -> PathBuf {
+        let source = self.build_dir.source_dir();
+        match self.krate.subpath() {
+            Some(subpath) => source.join(subpath),
+            None => source,
+        }
+    }
+
+    /// `cargo` of the task's toolchain, as used to build and test the crate.
+    ///
+    /// With the namespace backend it runs without network access and with a
+    /// read-only source directory, except for `cargo fix` which rewrites it.
+    pub(super) fn cargo(&self) -> Command {
+        let cargo = self.cargo_with_network();
+        match self.config.sandbox.backend() {
+            SandboxBackend::Process => cargo,
+            SandboxBackend::Namespace => {
+                let mut sandbox = Sandbox::new();
+                if self.experiment.mode != Mode::Fix {
+                    sandbox = sandbox.read_only(self.build_dir.source_dir());
+                }
+                cargo.sandbox(sandbox)
+            }
+        }
+    }
+
+    /// `cargo` of the task's toolchain, running inside the crate source with
+    /// a target directory dedicated to that toolchain, under the memory limit
+    /// and build timeout of the sandbox, with the OpenHarmony SDK wired up for
+    /// OHOS targets and the toolchain's flags and patches applied. Only meant
+    /// for fetching dependencies.
+    pub(super) fn cargo_with_network(&self) -> Command {
+        let target_dir = self
+            .build_dir
+            .target_dir(&self.toolchain.to_path_component());
+        let cap_lints = format!("--cap-lints={}", self.experiment.cap_lints);
+        let with_cap_lints = |flags: &Option<String>| match flags {
+            Some(flags) => format!("{} {}", cap_lints, flags),
+            None => cap_lints.clone(),
+        };
+
+        let cargo = match self.toolchain.source.install_dir() {
+            // Toolchains unknown to rustup are run directly, with their own
+            // tools first in the PATH for the cargo subcommands.
+            Some(dir) => {
+                let bin = dir.join("bin");
+                let paths = std::env::var_os("PATH").unwrap_or_default();
+                let path = std::env::join_paths(
+                    std::iter::once(bin.clone()).chain(std::env::split_paths(&paths)),
+                )
+                .unwrap_or(paths);
+                Command::new(bin.join("cargo"))
+                    .env("RUSTC", bin.join("rustc"))
+                    .env("RUSTDOC", bin.join("rustdoc"))
+                    .env("PATH", path)
+            }
+            None => Command::new("cargo").arg(format!(
+                "+{}",
+                self.toolchain.source.rustup_name().unwrap_or_default()
+            )),
+        };
+        let mut cargo = cargo
+            .args(patch_args(&self.toolchain.patches))
+            .cwd(self.crate_dir())
+            .env("CARGO_TARGET_DIR", target_dir)
+            .env("CARGO_INCREMENTAL", "0")
+            .env("RUST_BACKTRACE", "full")
+            .env("RUSTFLAGS", with_cap_lints(&self.toolchain.rustflags))
+            .env("RUSTDOCFLAGS", with_cap_lints(&self.toolchain.rustdocflags))
+            .limits(Limits {
+                timeout: Some(self.config.sandbox.build_timeout()),
+                memory: Some(self.config.sandbox.memory_limit.to_bytes()),
+                cgroup_root: self.config.sandbox.cgroup_root.clone(),
+            });
+
+        if let Some(target) = &self.toolchain.target {
+            for (key, value) in ohos::cargo_env(self.config.ohos.as_ref(), self.workspace, target) {
+                cargo = cargo.env(key, value);
+            }
+            if let Some(runner) = self.config.target_runners.get(target) {
+                let key = format!(
+                    "CARGO_TARGET_{}_RUNNER",
+                    target.to_uppercase().replace('-', "_")
+                );
+                cargo = cargo.env(key, runner);
+            }
+        }
+        cargo
+    }
+
+    /// Whether test binaries built for the task's target can be executed
+    /// directly on the host or through a configured runner. Targets with a
+    /// test executor are handled separately.
+    pub(super) fn can_run_tests(&self) -> bool {
+        match &self.toolchain.target {
+            None => true,
+            Some(target) => self.config.target_runners.contains_key(target) || host_can_run(target),
+        }
+    }
+
+    /// The executor configured to run the tests of the task's target, if any.
+    pub(super) fn test_executor(&self) -> Option<Box<dyn TestExecutor>> {
+        let target = self.toolchain.target.as_ref()?;
+        self.config.test_executors.get(target).map(executor::from_config)
+    }
+
+    /// Arguments selecting the compilation target, if the toolchain has one.
+    pub(super) fn target_args(&self) -> Vec<String> {
+        match self.toolchain.target {
+            Some(ref target) => vec!["--target".to_string(), target.clone()],
+            None => Vec::new(),
+        }
+    }
+
+    /// Extra arguments of the toolchain for the cargo subcommands building
+    /// the crate.
+    pub(super) fn cargoflags(&self) -> Vec<String> {
+        match self.toolchain.cargoflags {
+            Some(ref flags) => flags.split_whitespace().map(String::from).collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// `--config` arguments overriding the crates.io dependencies patched by a
+/// toolchain with their git repositories.
+fn patch_args(patches: &[CratePatch]) -> Vec<String> {
+    let mut args = Vec::new();
+    for patch in patches {
+        let key = format!("patch.crates-io.{}", patch.name);
+        let git = toml::Value::String(patch.repo.to_string());
+        let branch = toml::Value::String(patch.branch.clone());
+        args.push("--config".to_string());
+        args.push(format!("{}.git={}", key, git));
+        args.push("--config".to_string());
+        args.push(format!("{}.branch={}", key, branch));
+    }
+    args
+}
+
+/// Whether `target` is a variant of the host platform whose binaries run
+/// natively. OpenHarmony binaries need the OHOS dynamic loader, which hosts
+/// don't have.
+fn host_can_run(target: &str) -> bool {
+    target.split('-').next() == Some(std::env::consts::ARCH)
+        && target.contains(std::env::consts::OS)
+        && !is_ohos_target(target)
+}
+
 pub(super) enum TaskStep {
     BuildAndTest { tc: Toolchain, quiet: bool },
     BuildOnly { tc: Toolchain, quiet: bool },
@@ -27,6 +190,19 @@ pub(super) enum TaskStep {
 }
 
 impl TaskStep {
+    pub(super) fn for_mode(mode: Mode, tc: Toolchain) -> Self {
+        let quiet = false;
+        match mode {
+            Mode::BuildAndTest => TaskStep::BuildAndTest { tc, quiet },
+            Mode::BuildOnly => TaskStep::BuildOnly { tc, quiet },
+            Mode::CheckOnly => TaskStep::CheckOnly { tc, quiet },
+            Mode::Clippy => TaskStep::Clippy { tc, quiet },
+            Mode::Rustdoc => TaskStep::Rustdoc { tc, quiet },
+            Mode::UnstableFeatures => TaskStep::UnstableFeatures { tc },
+            Mode::Fix => TaskStep::Fix { tc, quiet },
+        }
+    }
+
     pub(super) fn toolchain(&self) -> &Toolchain {
         match self {
             TaskStep::BuildAndTest { tc, .. } => tc,
@@ -75,6 +251,86 @@ impl Task {
     pub(super) fn new(krate: Crate, step: TaskStep) -> Self {
         Task { krate, step }
     }
+
+    /// Execute the task and record its outcome through `api`.
+    pub(super) fn run(
+        &self,
+        config: &Config,
+        workspace: &Workspace,
+        build_dir: &BuildDirectory,
+        ex: &Experiment,
+        api: &dyn RecordProgress,
+    ) -> Fallible<()> {
+        let logs = LogStorage::from_config(&config.sandbox);
+        let ctx = TaskCtx {
+            config,
+            workspace,
+            build_dir,
+            logs: &logs,
+            experiment: ex,
+            toolchain: self.step.toolchain(),
+            krate: &self.krate,
+            quiet: self.step.is_quiet(),
+        };
+
+        let res = match self.step {
+            TaskStep::BuildAndTest { .. } => {
+                test::run_test("building and testing", &ctx, test::test_build_and_test)
+            }
+            TaskStep::BuildOnly { .. } => {
+                test::run_test("building", &ctx, test::test_build_only)
+            }
+            TaskStep::CheckOnly { .. } => {
+                test::run_test("checking", &ctx, test::test_check_only)
+            }
+            TaskStep::Clippy { .. } => {
+                test::run_test("linting", &ctx, test::test_clippy_only)
+            }
+            TaskStep::Rustdoc { .. } => {
+                test::run_test("documenting", &ctx, test::test_rustdoc)
+            }
+            TaskStep::UnstableFeatures { .. } => test::run_test(
+                "checking unstable features in",
+                &ctx,
+                test::find_unstable_features,
+            ),
+            TaskStep::Fix { .. } => test::run_test("fixing", &ctx, test::fix),
+        };
+
+        let result = match res {
+            Ok(result) => result,
+            Err(err) => {
+                logs.write_line(&format!("[ERROR] {:#}", err));
+                match err.downcast_ref::<OverrideResult>() {
+                    Some(OverrideResult(result)) => result.clone(),
+                    None => {
+                        error!("task {} failed: {:#}", self, err);
+                        TestResult::Error
+                    }
+                }
+            }
+        };
+
+        let patches = prepare::resolved_patches(&ctx).unwrap_or_else(|err| {
+            warn!("failed to read the patches resolved by {}: {:#}", self, err);
+            ResolvedPatches::new()
+        });
+        for (name, sha) in &patches {
+            logs.write_line(&format!("[INFO] patched {} resolved to {}", name, sha));
+        }
+
+        info!("{} => {:?}", self, result);
+        let log = logs.to_encoded()?;
+        api.record_progress(
+            ex,
+            &self.krate,
+            ctx.toolchain,
+            &log,
+            &result,
+            &patches,
+            None,
+        )
+    }
 }
 
 impl fmt::Display for Task {
@@ -109,6 +365,47 @@ mod tests {
         assert!(!step.is_quiet());
     }
 
+    #[test]
+    fn test_task_step_for_mode() {
+        let tc: Toolchain = "stable".parse().unwrap();
+        assert!(matches!(
+            TaskStep::for_mode(Mode::CheckOnly, tc.clone()),
+            TaskStep::CheckOnly { .. }
+        ));
+        assert!(matches!(
+            TaskStep::for_mode(Mode::UnstableFeatures, tc),
+            TaskStep::UnstableFeatures { .. }
+        ));
+    }
+
+    #[test]
+    fn test_patch_args() {
+        let tc: Toolchain = "stable+patch=libc=https://gitee.com/openharmony/libc#ohos"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            patch_args(&tc.patches),
+            vec![
+                "--config",
+                "patch.crates-io.libc.git=\"https://gitee.com/openharmony/libc\"",
+                "--config",
+                "patch.crates-io.libc.branch=\"ohos\"",
+            ]
+        );
+        assert!(patch_args(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_host_can_run() {
+        let host = format!("{}-unknown-{}-gnu", std::env::consts::ARCH, std::env::consts::OS);
+        assert!(host_can_run(&host));
+        assert!(!host_can_run(&format!(
+            "{}-unknown-linux-ohos",
+            std::env::consts::ARCH
+        )));
+        assert!(!host_can_run("wasm32-unknown-unknown"));
+    }
+
     #[test]
     fn test_task_display() {
         let tc = Toolchain {
@@ -1,29 +1,57 @@
 use crate::prelude::*;
-use crate::results::{BrokenReason, FailureReason, TestResult};
+use crate::results::{BrokenReason, DiagnosticCode, FailureReason, TestResult};
+use crate::runner::command::{CommandError, Stream};
+use crate::runner::executor::TestExecutor;
+use crate::runner::prepare;
 use crate::runner::tasks::TaskCtx;
 use crate::runner::OverrideResult;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-// Placeholder for rustwide types
-pub struct LogStorage;
-pub struct PrepareError;
+/// Tail of the output of the commands that failed in the chain of `err`.
+fn failed_output(err: &anyhow::Error) -> String {
+    let mut text = String::new();
+    for cause in err.chain() {
+        if let Some(CommandError::ExecutionFailed { output, .. }) = cause.downcast_ref() {
+            text.push('\n');
+            text.push_str(output);
+        }
+    }
+    text
+}
+
+/// Text used to classify an error: the error chain itself plus the tail of
+/// the output of the command that failed, if any.
+fn error_text(err: &anyhow::Error) -> String {
+    format!("{:#}{}", err, failed_output(err))
+}
+
+/// Whether `text` reports a crash of the compiler.
+fn is_ice(text: &str) -> bool {
+    text.contains("internal compiler error") || text.contains("thread 'rustc' panicked")
+}
 
 /// Extract failure reason from an error
 pub(crate) fn failure_reason(err: &anyhow::Error) -> FailureReason {
-    let err_string = format!("{:#}", err);
+    for cause in err.chain() {
+        match cause.downcast_ref::<CommandError>() {
+            Some(CommandError::OutOfMemory { .. }) => return FailureReason::OOM,
+            Some(CommandError::Timeout { .. }) => return FailureReason::Timeout,
+            Some(CommandError::NetworkAccess { .. }) => return FailureReason::NetworkAccess,
+            _ => {}
+        }
+    }
 
-    if err_string.contains("out of memory") || err_string.contains("OOM") {
-        FailureReason::OOM
-    } else if err_string.contains("no space left") || err_string.contains("disk full") {
+    // The output of a build mentions arbitrary words, only the compiler's own
+    // crash markers are looked for in it.
+    let err_string = format!("{:#}", err);
+    let output = failed_output(err);
+    let text = error_text(err);
+    if text.contains("no space left") || text.contains("disk full") {
         FailureReason::NoSpace
-    } else if err_string.contains("timeout") || err_string.contains("timed out") {
-        FailureReason::Timeout
-    } else if err_string.contains("internal compiler error")
-        || err_string.contains("ICE")
-        || err_string.contains("thread 'rustc' panicked")
-    {
+    } else if is_ice(&err_string) || err_string.contains("ICE") || is_ice(&output) {
         FailureReason::ICE
-    } else if err_string.contains("network") || err_string.contains("connection") {
-        FailureReason::NetworkAccess
     } else if err_string.contains("docker") || err_string.contains("container") {
         FailureReason::Docker
     } else {
@@ -34,7 +62,7 @@ pub(crate) fn failure_reason(err: &anyhow::Error) -> FailureReason {
 /// Detect if a crate is broken and convert error accordingly
 pub(super) fn detect_broken<T>(res: Result<T, anyhow::Error>) -> Result<T, anyhow::Error> {
     if let Err(ref err) = res {
-        let err_string = format!("{:#}", err);
+        let err_string = error_text(err);
 
         if err_string.contains("Cargo.toml") && err_string.contains("parse") {
             return Err(OverrideResult(TestResult::BrokenCrate(
@@ -60,84 +88,343 @@ pub(super) fn detect_broken<T>(res: Result<T, anyhow::Error>) -> Result<T, anyho
     res
 }
 
-/// Placeholder for actual test execution - would integrate with rustwide
+/// Prepare the crate and run `test_fn` on it.
+///
+/// Failures while preparing the crate never reach `test_fn`: they are
+/// reported as a broken crate when the cause can be identified, or as a
+/// `PrepareFail` otherwise.
 pub(super) fn run_test(
     action: &str,
     ctx: &TaskCtx,
     test_fn: fn(&TaskCtx) -> Fallible<TestResult>,
 ) -> Fallible<TestResult> {
     info!(
-        "Running {} for crate {} with toolchain {}",
+        "{} crate {} with toolchain {}",
         action, ctx.krate, ctx.toolchain
     );
+    ctx.logs.write_line(&format!(
+        "[INFO] {} {} with {}",
+        action, ctx.krate, ctx.toolchain
+    ));
+
+    if let Err(err) = detect_broken(prepare::prepare(ctx)) {
+        if err.is::<OverrideResult>() {
+            return Err(err);
+        }
+        ctx.logs.write_line(&format!("[ERROR] failed to prepare {}: {:#}", ctx.krate, err));
+        return Ok(TestResult::PrepareFail(failure_reason(&err)));
+    }
 
-    // In real implementation, this would set up logging, build environment, etc.
-    let result = detect_broken(test_fn(ctx))?;
-    Ok(result)
+    test_fn(ctx)
+}
+
+/// Run a cargo subcommand emitting JSON diagnostics, storing the rendered
+/// diagnostics in the log. On failure the error codes reported by rustc are
+/// returned alongside the error.
+fn run_cargo(ctx: &TaskCtx, args: &[&str]) -> Result<(), (Error, BTreeSet<DiagnosticCode>)> {
+    let mut codes = BTreeSet::new();
+    let res = ctx
+        .cargo()
+        .args(args)
+        .args(["--frozen", "--message-format=json"])
+        .args(ctx.target_args())
+        .args(ctx.cargoflags())
+        .run_with(ctx.logs, |stream, line| {
+            if stream == Stream::Stdout {
+                process_json_message(line, &mut codes)
+            } else {
+                Some(line.to_string())
+            }
+        });
+    res.map_err(|err| (err, codes))
+}
+
+/// Turn a line of `--message-format=json` output into the text to log.
+fn process_json_message(line: &str, codes: &mut BTreeSet<DiagnosticCode>) -> Option<String> {
+    let Ok(message) = serde_json::from_str::<serde_json::Value>(line) else {
+        return Some(line.to_string());
+    };
+
+    match message["reason"].as_str() {
+        Some("compiler-message") => {
+            let diagnostic = &message["message"];
+            if diagnostic["level"] == "error" {
+                if let Some(code) = diagnostic["code"]["code"].as_str() {
+                    codes.insert(DiagnosticCode::new(code));
+                }
+            }
+            diagnostic["rendered"]
+                .as_str()
+                .map(|rendered| rendered.trim_end().to_string())
+        }
+        Some(_) => None,
+        None => Some(line.to_string()),
+    }
+}
+
+fn build_failure(err: &Error, codes: BTreeSet<DiagnosticCode>) -> FailureReason {
+    match failure_reason(err) {
+        // Hitting a sandbox limit is what failed the build, even if errors
+        // were reported before it was killed.
+        reason @ (FailureReason::OOM
+        | FailureReason::Timeout
+        | FailureReason::NetworkAccess) => reason,
+        reason if codes.is_empty() => reason,
+        _ => FailureReason::CompilerError(codes),
+    }
+}
+
+/// Build the crate and its tests without running them.
+fn build(ctx: &TaskCtx) -> Result<(), FailureReason> {
+    run_cargo(ctx, &["build"])
+        .and_then(|_| run_cargo(ctx, &["test", "--no-run"]))
+        .map_err(|(err, codes)| build_failure(&err, codes))
 }
 
 /// Build and test a crate
 pub(super) fn test_build_and_test(ctx: &TaskCtx) -> Fallible<TestResult> {
-    info!("Building and testing crate {}", ctx.krate);
+    if let Err(reason) = build(ctx) {
+        return Ok(TestResult::BuildFail(reason));
+    }
 
-    // Placeholder implementation
-    // In real implementation, this would:
-    // 1. Prepare the build environment
-    // 2. Run cargo build
-    // 3. Run cargo test
-    // 4. Parse output and return appropriate TestResult
+    if let Some(executor) = ctx.test_executor() {
+        return run_tests_with(ctx, executor.as_ref());
+    }
 
-    Ok(TestResult::TestPass)
+    if !ctx.can_run_tests() {
+        ctx.logs.write_line(&format!(
+            "[INFO] no runner configured for target {}, the tests were built but not run",
+            ctx.toolchain.target.as_deref().unwrap_or_default()
+        ));
+        return Ok(TestResult::TestNotRunnable);
+    }
+
+    let mut cargo = ctx
+        .cargo()
+        .args(["test", "--frozen"])
+        .args(ctx.target_args())
+        .args(ctx.cargoflags());
+    if ctx.quiet {
+        cargo = cargo.arg("--quiet");
+    }
+    let mut not_executed = false;
+    let res = cargo.run_with(ctx.logs, |_, line| {
+        not_executed |= is_exec_failure(line);
+        Some(line.to_string())
+    });
+    match res {
+        Ok(()) => Ok(TestResult::TestPass),
+        Err(err) => match failure_reason(&err) {
+            FailureReason::Unknown if not_executed => Ok(TestResult::TestNotRunnable),
+            reason => Ok(TestResult::TestFail(reason)),
+        },
+    }
+}
+
+/// Run every test binary of the crate through `executor`. Failures of the
+/// executor itself are errors rather than test failures.
+fn run_tests_with(ctx: &TaskCtx, executor: &dyn TestExecutor) -> Fallible<TestResult> {
+    let binaries = test_binaries(ctx)?;
+    ctx.logs.write_line(&format!(
+        "[INFO] running {} test binaries on {}",
+        binaries.len(),
+        executor.name()
+    ));
+
+    let mut success = true;
+    for binary in &binaries {
+        ctx.logs
+            .write_line(&format!("[INFO] running `{}`", binary.display()));
+        let handle = executor.push(binary)?;
+        let res = executor.run(&handle);
+        if let Err(err) = executor.cleanup(&handle) {
+            warn!("failed to clean up {} on {}: {:#}", handle, executor.name(), err);
+        }
+
+        let output = res?;
+        for line in output.output.lines() {
+            ctx.logs.write_line(line);
+        }
+        success &= output.success;
+    }
+
+    if success {
+        Ok(TestResult::TestPass)
+    } else {
+        Ok(TestResult::TestFail(FailureReason::Unknown))
+    }
+}
+
+/// Paths of the test binaries of the crate, which must already be built.
+fn test_binaries(ctx: &TaskCtx) -> Fallible<Vec<PathBuf>> {
+    let mut binaries = Vec::new();
+    let mut codes = BTreeSet::new();
+    ctx.cargo()
+        .args(["test", "--no-run", "--frozen", "--message-format=json"])
+        .args(ctx.target_args())
+        .args(ctx.cargoflags())
+        .run_with(ctx.logs, |stream, line| {
+            if stream == Stream::Stderr {
+                return Some(line.to_string());
+            }
+            if let Some(binary) = test_executable(line) {
+                binaries.push(binary);
+            }
+            process_json_message(line, &mut codes)
+        })?;
+    Ok(binaries)
+}
+
+/// The test executable produced by a `compiler-artifact` message, if any.
+fn test_executable(line: &str) -> Option<PathBuf> {
+    let message = serde_json::from_str::<serde_json::Value>(line).ok()?;
+    if message["reason"] != "compiler-artifact" || message["profile"]["test"] != true {
+        return None;
+    }
+    message["executable"].as_str().map(PathBuf::from)
+}
+
+/// Whether a line of `cargo test` output shows that a test binary could not
+/// be started at all, as opposed to a test failing.
+fn is_exec_failure(line: &str) -> bool {
+    line.contains("(never executed)") || line.contains("Exec format error")
 }
 
 /// Build a crate without running tests
 pub(super) fn test_build_only(ctx: &TaskCtx) -> Fallible<TestResult> {
-    info!("Building crate {} (no tests)", ctx.krate);
+    match build(ctx) {
+        Ok(()) => Ok(TestResult::TestSkipped),
+        Err(reason) => Ok(TestResult::BuildFail(reason)),
+    }
+}
 
-    // Placeholder implementation
-    Ok(TestResult::TestPass)
+fn check_result(res: Result<(), (Error, BTreeSet<DiagnosticCode>)>) -> TestResult {
+    match res {
+        Ok(()) => TestResult::TestPass,
+        Err((err, codes)) => TestResult::BuildFail(build_failure(&err, codes)),
+    }
 }
 
 /// Check a crate without building
 pub(super) fn test_check_only(ctx: &TaskCtx) -> Fallible<TestResult> {
-    info!("Checking crate {}", ctx.krate);
-
-    // Placeholder implementation
-    Ok(TestResult::TestPass)
+    Ok(check_result(run_cargo(
+        ctx,
+        &["check", "--workspace", "--all-targets"],
+    )))
 }
 
 /// Run clippy on a crate
 pub(super) fn test_clippy_only(ctx: &TaskCtx) -> Fallible<TestResult> {
-    info!("Running clippy on crate {}", ctx.krate);
-
-    // Placeholder implementation
-    Ok(TestResult::TestPass)
+    Ok(check_result(run_cargo(
+        ctx,
+        &["clippy", "--workspace", "--all-targets"],
+    )))
 }
 
 /// Generate rustdoc for a crate
 pub(super) fn test_rustdoc(ctx: &TaskCtx) -> Fallible<TestResult> {
-    info!("Generating rustdoc for crate {}", ctx.krate);
-
-    // Placeholder implementation
-    Ok(TestResult::TestPass)
+    Ok(check_result(run_cargo(
+        ctx,
+        &["doc", "--no-deps", "--document-private-items"],
+    )))
 }
 
 /// Run cargo fix on a crate
 pub(super) fn fix(ctx: &TaskCtx) -> Fallible<TestResult> {
-    info!("Running cargo fix on crate {}", ctx.krate);
+    let mut fix_failed = false;
+    let res = ctx
+        .cargo()
+        .args([
+            "fix",
+            "--frozen",
+            "--allow-no-vcs",
+            "--allow-dirty",
+            "--workspace",
+            "--all-targets",
+        ])
+        .args(ctx.target_args())
+        .args(ctx.cargoflags())
+        .run_with(ctx.logs, |_, line| {
+            if line.contains("failed to automatically apply fixes suggested by rustc") {
+                fix_failed = true;
+            }
+            Some(line.to_string())
+        });
+
+    match res {
+        Ok(()) if fix_failed => Ok(TestResult::BuildFail(FailureReason::Unknown)),
+        Ok(()) => Ok(TestResult::TestPass),
+        Err(err) => Ok(TestResult::BuildFail(failure_reason(&err))),
+    }
+}
 
-    // Placeholder implementation
+/// Record the unstable features enabled by the crate in its log
+pub(super) fn find_unstable_features(ctx: &TaskCtx) -> Fallible<TestResult> {
+    let mut features = BTreeSet::new();
+    collect_unstable_features(&ctx.crate_dir(), &mut features)?;
+
+    if features.is_empty() {
+        ctx.logs.write_line("[INFO] no unstable features used");
+    } else {
+        ctx.logs.write_line(&format!(
+            "[INFO] unstable features used: {}",
+            features.into_iter().collect::<Vec<_>>().join(", ")
+        ));
+    }
     Ok(TestResult::TestPass)
 }
 
+fn collect_unstable_features(dir: &Path, features: &mut BTreeSet<String>) -> Fallible<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            if entry.file_name() != ".git" && entry.file_name() != "target" {
+                collect_unstable_features(&path, features)?;
+            }
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            let content = String::from_utf8_lossy(&fs::read(&path)?).into_owned();
+            features.extend(parse_unstable_features(&content));
+        }
+    }
+    Ok(())
+}
+
+/// Parse the features enabled by `#![feature(...)]` attributes.
+fn parse_unstable_features(source: &str) -> Vec<String> {
+    let mut features = Vec::new();
+    let mut rest = source;
+    while let Some(start) = rest.find("#![feature(") {
+        rest = &rest[start + "#![feature(".len()..];
+        let Some(end) = rest.find(')') else { break };
+        features.extend(
+            rest[..end]
+                .split(',')
+                .map(str::trim)
+                .filter(|feature| !feature.is_empty())
+                .map(String::from),
+        );
+        rest = &rest[end..];
+    }
+    features
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_failure_reason_oom() {
-        let err = anyhow::anyhow!("process killed: out of memory");
+        let err: anyhow::Error = CommandError::OutOfMemory {
+            command: "cargo build".to_string(),
+            limit: 1024,
+        }
+        .into();
         assert_eq!(failure_reason(&err), FailureReason::OOM);
+
+        // Only the sandbox decides whether a build ran out of memory.
+        let err = anyhow::anyhow!("process killed: out of memory");
+        assert_eq!(failure_reason(&err), FailureReason::Unknown);
     }
 
     #[test]
@@ -148,8 +435,29 @@ mod tests {
 
     #[test]
     fn test_failure_reason_timeout() {
-        let err = anyhow::anyhow!("operation timed out");
+        let err: anyhow::Error = CommandError::Timeout {
+            command: "cargo test".to_string(),
+            timeout: std::time::Duration::from_secs(900),
+        }
+        .into();
+        let err = err.context("failed to run the tests");
         assert_eq!(failure_reason(&err), FailureReason::Timeout);
+
+        let err = anyhow::anyhow!("operation timed out");
+        assert_eq!(failure_reason(&err), FailureReason::Unknown);
+    }
+
+    #[test]
+    fn test_failure_reason_network_access() {
+        let err: anyhow::Error = CommandError::NetworkAccess {
+            command: "cargo build".to_string(),
+            evidence: "Could not resolve host: example.com".to_string(),
+        }
+        .into();
+        assert_eq!(failure_reason(&err), FailureReason::NetworkAccess);
+
+        let err = anyhow::anyhow!("connection reset by peer");
+        assert_eq!(failure_reason(&err), FailureReason::Unknown);
     }
 
     #[test]
@@ -197,4 +505,79 @@ mod tests {
             panic!("Expected OverrideResult");
         }
     }
+
+    #[test]
+    fn test_failure_reason_from_command_output() {
+        let err: anyhow::Error = CommandError::ExecutionFailed {
+            command: "cargo build".to_string(),
+            status: std::process::Command::new("false").status().unwrap(),
+            output: "error: internal compiler error: unexpected panic".to_string(),
+        }
+        .into();
+        assert_eq!(failure_reason(&err), FailureReason::ICE);
+
+        // Words of a normal compile error must not be mistaken for markers.
+        let err: anyhow::Error = CommandError::ExecutionFailed {
+            command: "cargo build".to_string(),
+            status: std::process::Command::new("false").status().unwrap(),
+            output: "NOTICE: building docker-api in a container\n\
+                     error[E0425]: cannot find value `x` in this scope"
+                .to_string(),
+        }
+        .into();
+        assert_eq!(failure_reason(&err), FailureReason::Unknown);
+    }
+
+    #[test]
+    fn test_test_executable() {
+        let line = r#"{"reason":"compiler-artifact","profile":{"test":true},"executable":"/target/debug/deps/demo-1234"}"#;
+        assert_eq!(
+            test_executable(line),
+            Some(PathBuf::from("/target/debug/deps/demo-1234"))
+        );
+
+        let line = r#"{"reason":"compiler-artifact","profile":{"test":false},"executable":"/target/debug/demo"}"#;
+        assert_eq!(test_executable(line), None);
+        let line = r#"{"reason":"compiler-artifact","profile":{"test":true},"executable":null}"#;
+        assert_eq!(test_executable(line), None);
+        assert_eq!(test_executable("not json"), None);
+    }
+
+    #[test]
+    fn test_is_exec_failure() {
+        assert!(is_exec_failure(
+            "error: could not execute process `target/debug/deps/demo-1234` (never executed)"
+        ));
+        assert!(is_exec_failure("Caused by: Exec format error (os error 8)"));
+        assert!(!is_exec_failure("test tests::it_works ... FAILED"));
+    }
+
+    #[test]
+    fn test_process_json_message() {
+        let mut codes = BTreeSet::new();
+        let line = r#"{"reason":"compiler-message","message":{"level":"error","code":{"code":"E0308"},"rendered":"error[E0308]: mismatched types\n"}}"#;
+        assert_eq!(
+            process_json_message(line, &mut codes),
+            Some("error[E0308]: mismatched types".to_string())
+        );
+        assert!(codes.contains(&DiagnosticCode::new("E0308")));
+
+        let line = r#"{"reason":"compiler-artifact","target":{}}"#;
+        assert_eq!(process_json_message(line, &mut codes), None);
+
+        assert_eq!(
+            process_json_message("   Compiling foo v0.1.0", &mut codes),
+            Some("   Compiling foo v0.1.0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_unstable_features() {
+        let source = "#![feature(never_type, try_blocks)]\n#![feature(box_patterns)]\nfn main() {}";
+        assert_eq!(
+            parse_unstable_features(source),
+            vec!["never_type", "try_blocks", "box_patterns"]
+        );
+        assert!(parse_unstable_features("fn main() {}").is_empty());
+    }
 }
@@ -0,0 +1,190 @@
+use crate::config::Config;
+use crate::experiments::{Experiment, Mode};
+use crate::prelude::*;
+use crate::runner::command::Command;
+use crate::runner::logs::LogStorage;
+use crate::toolchain::{RustwideToolchain, Toolchain, UnusableToolchain};
+
+/// A tool some modes need on top of `rustc` and `cargo`.
+struct Tool {
+    /// Binary installed in the `bin` directory of the toolchain.
+    binary: &'static str,
+    /// Rustup component providing it, if it doesn't ship with rustc.
+    component: Option<&'static str>,
+}
+
+fn required_tools(mode: Mode) -> &'static [Tool] {
+    match mode {
+        Mode::Clippy => &[Tool {
+            binary: "cargo-clippy",
+            component: Some("clippy"),
+        }],
+        Mode::Rustdoc => &[Tool {
+            binary: "rustdoc",
+            component: None,
+        }],
+        _ => &[],
+    }
+}
+
+/// Make sure both toolchains of `ex` are installed, with the standard library
+/// of their target and the tools its mode needs, before any crate is built.
+///
+/// Toolchains from rustup and CI builds are installed when missing, through
+/// the configured mirror if any; local toolchains can only be checked. A
+/// toolchain that can't be made usable fails the whole experiment.
+pub(super) fn setup(config: &Config, ex: &Experiment) -> Fallible<()> {
+    let tools = required_tools(ex.mode);
+    for toolchain in &ex.toolchains {
+        ensure(config, toolchain, tools).map_err(|err| UnusableToolchain {
+            toolchain: toolchain.to_string(),
+            reason: format!("{:#}", err),
+        })?;
+    }
+    Ok(())
+}
+
+fn ensure(config: &Config, toolchain: &Toolchain, tools: &[Tool]) -> Fallible<()> {
+    let binaries = tools.iter().map(|tool| tool.binary).collect::<Vec<_>>();
+    let missing = match toolchain.missing(&binaries) {
+        Ok(missing) if missing.is_empty() => return Ok(()),
+        Ok(missing) => missing.join(", "),
+        Err(err) => format!("{:#}", err),
+    };
+
+    let local = matches!(
+        toolchain.source,
+        RustwideToolchain::Path(_) | RustwideToolchain::Link(_)
+    );
+    if local || config.offline {
+        anyhow::bail!("missing {}", missing);
+    }
+
+    info!("installing toolchain {} (missing {})", toolchain, missing);
+    let logs = LogStorage::new();
+    install(config, toolchain, tools, &logs)?;
+
+    let missing = toolchain.missing(&binaries)?;
+    if !missing.is_empty() {
+        anyhow::bail!("missing {} after installing it", missing.join(", "));
+    }
+    Ok(())
+}
+
+fn install(
+    config: &Config,
+    toolchain: &Toolchain,
+    tools: &[Tool],
+    logs: &LogStorage,
+) -> Fallible<()> {
+    let mirror = config.toolchains.mirror.as_deref();
+    let components = tools.iter().filter_map(|tool| tool.component);
+
+    let (sha, alt) = match &toolchain.source {
+        RustwideToolchain::Dist(name) => {
+            let rustup = || {
+                let cmd = Command::new("rustup").env("RUSTUP_AUTO_INSTALL", "0");
+                match mirror {
+                    Some(mirror) => cmd.env("RUSTUP_DIST_SERVER", mirror),
+                    None => cmd,
+                }
+            };
+            // Installed toolchains are completed rather than updated, so that
+            // the compiler doesn't change under a running experiment.
+            if toolchain.source.sysroot().is_err() {
+                rustup()
+                    .args([
+                        "toolchain",
+                        "install",
+                        name,
+                        "--profile",
+                        "minimal",
+                        "--no-self-update",
+                    ])
+                    .run(logs)?;
+            }
+            if let Some(ref target) = toolchain.target {
+                rustup()
+                    .args(["target", "add", "--toolchain", name, target])
+                    .run(logs)?;
+            }
+            for component in components {
+                rustup()
+                    .args(["component", "add", "--toolchain", name, component])
+                    .run(logs)?;
+            }
+            return Ok(());
+        }
+        RustwideToolchain::Master { sha: Some(sha) } | RustwideToolchain::Try { sha } => {
+            (sha, false)
+        }
+        RustwideToolchain::CI { sha, alt } => (sha, *alt),
+        RustwideToolchain::Master { sha: None } => {
+            anyhow::bail!("a master toolchain can only be installed for a given commit")
+        }
+        RustwideToolchain::Path(_) | RustwideToolchain::Link(_) => unreachable!(),
+    };
+
+    // CI builds aren't published as rustup channels.
+    let mut cmd = Command::new("rustup-toolchain-install-master")
+        .arg(sha)
+        .arg("--force");
+    if alt {
+        cmd = cmd.arg("--alt");
+    }
+    if let Some(mirror) = mirror {
+        cmd = cmd.args(["--server", mirror]);
+    }
+    for component in components {
+        cmd = cmd.args(["--component", component]);
+    }
+    if let Some(ref target) = toolchain.target {
+        cmd = cmd.args(["--targets", target]);
+    }
+    cmd.run(logs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn test_config() -> Config {
+        toml::from_str(include_str!("../../config.toml")).unwrap()
+    }
+
+    fn fake_toolchain(dir: &std::path::Path, binaries: &[&str]) {
+        fs::create_dir_all(dir.join("bin")).unwrap();
+        for binary in binaries {
+            let name = format!("{}{}", binary, std::env::consts::EXE_SUFFIX);
+            fs::write(dir.join("bin").join(name), "").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_ensure_local_toolchain() {
+        let dir = tempfile::tempdir().unwrap();
+        fake_toolchain(dir.path(), &["rustc", "cargo"]);
+        let toolchain: Toolchain = format!("path:{}", dir.path().display()).parse().unwrap();
+        let config = test_config();
+
+        ensure(&config, &toolchain, required_tools(Mode::BuildAndTest)).unwrap();
+
+        // Local toolchains are never installed into.
+        let err = ensure(&config, &toolchain, required_tools(Mode::Clippy)).unwrap_err();
+        assert_eq!(err.to_string(), "missing cargo-clippy");
+
+        fake_toolchain(dir.path(), &["cargo-clippy"]);
+        ensure(&config, &toolchain, required_tools(Mode::Clippy)).unwrap();
+    }
+
+    #[test]
+    fn test_ensure_offline() {
+        let mut config = test_config();
+        config.offline = true;
+        let toolchain: Toolchain = "crater-missing-toolchain".parse().unwrap();
+
+        let err = ensure(&config, &toolchain, &[]).unwrap_err();
+        assert!(err.to_string().starts_with("missing "));
+    }
+}
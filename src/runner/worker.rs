@@ -1,23 +1,25 @@
+use crate::config::Config;
 use crate::crates::Crate;
 use crate::experiments::Experiment;
 use crate::prelude::*;
-use crate::results::TestResult;
+use crate::results::{EncodedLog, ResolvedPatches, TestResult};
+use crate::runner::tasks::{Task, TaskStep};
+use crate::runner::workspace::{BuildDirectory, Workspace};
 use crate::toolchain::Toolchain;
 use std::sync::{Arc, Mutex, Condvar, atomic::{AtomicBool, AtomicUsize, Ordering}};
 use std::time::Duration;
 
-// Placeholder for rustwide Workspace
-pub struct Workspace;
-
 /// Trait for recording progress of test runs
 pub trait RecordProgress: Send + Sync {
+    #[allow(clippy::too_many_arguments)]
     fn record_progress(
         &self,
         ex: &Experiment,
         krate: &Crate,
         toolchain: &Toolchain,
-        log: &[u8],
+        log: &EncodedLog,
         result: &TestResult,
+        patches: &ResolvedPatches,
         version: Option<(&Crate, &Crate)>,
     ) -> Fallible<()>;
 }
@@ -26,7 +28,11 @@ pub trait RecordProgress: Send + Sync {
 pub(super) struct Worker<'a> {
     name: String,
     workspace: &'a Workspace,
+    build_dir: BuildDirectory,
     ex: &'a Experiment,
+    config: &'a Config,
+    api: &'a dyn RecordProgress,
+    next_crate: &'a (dyn Fn() -> Fallible<Option<Crate>> + Send + Sync),
     disk_space_watcher: Arc<DiskSpaceWatcher>,
 }
 
@@ -35,12 +41,19 @@ impl<'a> Worker<'a> {
         name: String,
         workspace: &'a Workspace,
         ex: &'a Experiment,
+        config: &'a Config,
+        api: &'a dyn RecordProgress,
+        next_crate: &'a (dyn Fn() -> Fallible<Option<Crate>> + Send + Sync),
         disk_space_watcher: Arc<DiskSpaceWatcher>,
     ) -> Self {
         Worker {
+            build_dir: workspace.build_dir(&name),
             name,
             workspace,
             ex,
+            config,
+            api,
+            next_crate,
             disk_space_watcher,
         }
     }
@@ -49,18 +62,35 @@ impl<'a> Worker<'a> {
         &self.name
     }
 
+    /// Process crates until `next_crate` runs out, testing each of them with
+    /// both toolchains of the experiment.
+    ///
+    /// Between two crates the worker stops while the disk space watcher has
+    /// paused the workers, and it is reported as permanently idle once it
+    /// exits, successfully or not.
     pub(super) fn run(&self) -> Fallible<()> {
         info!("Worker {} started", self.name);
+        let res = self.process_crates();
+        self.disk_space_watcher.worker_idle(true);
+        info!("Worker {} finished", self.name);
+        res
+    }
+
+    fn process_crates(&self) -> Fallible<()> {
+        loop {
+            self.disk_space_watcher.wait_if_paused();
 
-        // Placeholder implementation
-        // In real implementation, this would:
-        // 1. Get next task from queue
-        // 2. Execute the task
-        // 3. Record results
-        // 4. Check disk space
-        // 5. Repeat until no more tasks
+            let Some(krate) = (self.next_crate)()? else {
+                return Ok(());
+            };
 
-        Ok(())
+            for tc in &self.ex.toolchains {
+                let task = Task::new(krate.clone(), TaskStep::for_mode(self.ex.mode, tc.clone()));
+                task.run(self.config, self.workspace, &self.build_dir, self.ex, self.api)?;
+            }
+
+            self.build_dir.purge()?;
+        }
     }
 }
 
@@ -72,6 +102,7 @@ pub(super) struct DiskSpaceWatcher {
     idle_workers: AtomicUsize,
     permanent_idle_workers: AtomicUsize,
     stop_requested: AtomicBool,
+    pause_requested: AtomicBool,
     condvar: Condvar,
     mutex: Mutex<()>,
 }
@@ -85,17 +116,19 @@ impl DiskSpaceWatcher {
             idle_workers: AtomicUsize::new(0),
             permanent_idle_workers: AtomicUsize::new(0),
             stop_requested: AtomicBool::new(false),
+            pause_requested: AtomicBool::new(false),
             condvar: Condvar::new(),
             mutex: Mutex::new(()),
         })
     }
 
     pub(super) fn stop(&self) {
+        let _guard = self.mutex.lock().unwrap();
         self.stop_requested.store(true, Ordering::SeqCst);
         self.condvar.notify_all();
     }
 
-    pub(super) fn run(&self, _workspace: &Workspace) {
+    pub(super) fn run(&self, workspace: &Workspace) {
         info!(
             "Disk space watcher started (interval: {:?}, threshold: {:.0}%)",
             self.interval,
@@ -107,32 +140,104 @@ impl DiskSpaceWatcher {
                 break;
             }
 
-            // Check disk space
-            // In real implementation, this would check actual disk usage
-            // For now, using a conservative placeholder value for safety
-            let disk_usage = 0.0; // Placeholder - always below threshold
-
-            if disk_usage > self.threshold {
-                warn!(
-                    "Disk usage above threshold: {:.0}% > {:.0}%",
-                    disk_usage * 100.0,
-                    self.threshold * 100.0
-                );
-                // Would pause workers here
-            }
+            self.check(workspace);
 
             // Wait for next check or stop signal
             let guard = self.mutex.lock().unwrap();
             let _ = self
                 .condvar
-                .wait_timeout(guard, self.interval)
+                .wait_timeout_while(guard, self.interval, |_| {
+                    !self.stop_requested.load(Ordering::SeqCst)
+                })
                 .unwrap();
         }
 
         info!("Disk space watcher stopped");
     }
 
+    /// Measure the disk usage of the workspace, and if it is above the
+    /// threshold pause the workers, purge the workspace and resume them.
+    fn check(&self, workspace: &Workspace) {
+        let disk_usage = match workspace.disk_usage() {
+            Ok(usage) => usage,
+            Err(err) => {
+                error!("failed to measure disk usage: {:#}", err);
+                return;
+            }
+        };
+
+        if disk_usage <= self.threshold {
+            debug!("Disk usage at {:.0}%", disk_usage * 100.0);
+            return;
+        }
+
+        warn!(
+            "Disk usage above threshold: {:.0}% > {:.0}%, cleaning up the workspace",
+            disk_usage * 100.0,
+            self.threshold * 100.0
+        );
+
+        self.pause_workers();
+        if let Err(err) = workspace.purge_all_build_dirs() {
+            error!("failed to purge build directories: {:#}", err);
+        }
+        if let Err(err) = workspace.purge_sources() {
+            error!("failed to purge cached sources: {:#}", err);
+        }
+        self.resume_workers();
+
+        match workspace.disk_usage() {
+            Ok(usage) if usage > self.threshold => warn!(
+                "Disk usage still above threshold after cleanup: {:.0}%",
+                usage * 100.0
+            ),
+            Ok(usage) => info!("Disk usage after cleanup: {:.0}%", usage * 100.0),
+            Err(err) => error!("failed to measure disk usage: {:#}", err),
+        }
+    }
+
+    /// Ask every worker to stop before its next crate, and wait until all of
+    /// them are idle (or the watcher is stopped).
+    fn pause_workers(&self) {
+        let mut guard = self.mutex.lock().unwrap();
+        self.pause_requested.store(true, Ordering::SeqCst);
+        info!("Pausing workers");
+
+        while self.idle_workers.load(Ordering::SeqCst)
+            + self.permanent_idle_workers.load(Ordering::SeqCst)
+            < self.worker_count
+            && !self.stop_requested.load(Ordering::SeqCst)
+        {
+            guard = self.condvar.wait(guard).unwrap();
+        }
+    }
+
+    fn resume_workers(&self) {
+        let _guard = self.mutex.lock().unwrap();
+        self.pause_requested.store(false, Ordering::SeqCst);
+        self.condvar.notify_all();
+        info!("Resuming workers");
+    }
+
+    /// Called by workers between two crates: blocks for as long as the
+    /// watcher keeps the workers paused.
+    pub(super) fn wait_if_paused(&self) {
+        if !self.pause_requested.load(Ordering::SeqCst) {
+            return;
+        }
+
+        self.worker_idle(false);
+        {
+            let mut guard = self.mutex.lock().unwrap();
+            while self.pause_requested.load(Ordering::SeqCst) {
+                guard = self.condvar.wait(guard).unwrap();
+            }
+        }
+        self.worker_active();
+    }
+
     pub(super) fn worker_idle(&self, permanent: bool) {
+        let _guard = self.mutex.lock().unwrap();
         if permanent {
             let count = self.permanent_idle_workers.fetch_add(1, Ordering::SeqCst) + 1;
             debug!("Worker permanently idle ({}/{})", count, self.worker_count);
@@ -140,6 +245,7 @@ impl DiskSpaceWatcher {
             let count = self.idle_workers.fetch_add(1, Ordering::SeqCst) + 1;
             debug!("Worker idle ({}/{})", count, self.worker_count);
         }
+        self.condvar.notify_all();
     }
 
     pub(super) fn worker_active(&self) {
@@ -193,4 +299,56 @@ mod tests {
         watcher.stop();
         assert!(watcher.stop_requested.load(Ordering::SeqCst));
     }
+
+    #[test]
+    fn test_pause_waits_for_idle_workers() {
+        let watcher = DiskSpaceWatcher::new(Duration::from_secs(30), 0.8, 2);
+
+        std::thread::scope(|scope| {
+            let pauser = scope.spawn(|| watcher.pause_workers());
+
+            while !watcher.pause_requested.load(Ordering::SeqCst) {
+                std::thread::yield_now();
+            }
+
+            // One worker exits for good, the other one waits for the resume.
+            watcher.worker_idle(true);
+            let waiter = scope.spawn(|| watcher.wait_if_paused());
+
+            pauser.join().unwrap();
+            assert_eq!(watcher.idle_workers.load(Ordering::SeqCst), 1);
+
+            watcher.resume_workers();
+            waiter.join().unwrap();
+            assert_eq!(watcher.idle_workers.load(Ordering::SeqCst), 0);
+        });
+    }
+
+    #[test]
+    fn test_wait_if_paused_without_pause() {
+        let watcher = DiskSpaceWatcher::new(Duration::from_secs(30), 0.8, 1);
+        watcher.wait_if_paused();
+        assert_eq!(watcher.idle_workers.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_check_purges_workspace_above_threshold() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(dir.path()).unwrap();
+        let cached = workspace.sources_dir().join("reg/serde/1.0.0");
+        std::fs::create_dir_all(&cached).unwrap();
+
+        // Below the threshold nothing is touched.
+        let watcher = DiskSpaceWatcher::new(Duration::from_secs(30), 1.0, 1);
+        watcher.check(&workspace);
+        assert!(cached.is_dir());
+
+        // Every filesystem in use is above a threshold of zero.
+        let watcher = DiskSpaceWatcher::new(Duration::from_secs(30), 0.0, 1);
+        watcher.worker_idle(true);
+        watcher.check(&workspace);
+        assert!(!cached.exists());
+        assert!(workspace.sources_dir().is_dir());
+        assert!(!watcher.pause_requested.load(Ordering::SeqCst));
+    }
 }
@@ -0,0 +1,154 @@
+use crate::prelude::*;
+use crate::utils::fs::remove_dir_all;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// On-disk layout used by the runner.
+///
+/// ```text
+/// <root>/sources/   fetched crate sources, shared between workers
+/// <root>/cache/     downloaded `.crate` files and git mirrors, kept across purges
+/// <root>/builds/    one build directory per worker
+/// <root>/tools/     helper programs generated for the builds
+/// ```
+pub struct Workspace {
+    root: PathBuf,
+}
+
+impl Workspace {
+    pub fn new<P: AsRef<Path>>(root: P) -> Fallible<Self> {
+        let workspace = Workspace {
+            root: root.as_ref().to_path_buf(),
+        };
+        fs::create_dir_all(workspace.sources_dir())?;
+        fs::create_dir_all(workspace.cache_dir())?;
+        fs::create_dir_all(workspace.builds_dir())?;
+        fs::create_dir_all(workspace.tools_dir())?;
+        Ok(workspace)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn sources_dir(&self) -> PathBuf {
+        self.root.join("sources")
+    }
+
+    pub fn cache_dir(&self) -> PathBuf {
+        self.root.join("cache")
+    }
+
+    pub fn builds_dir(&self) -> PathBuf {
+        self.root.join("builds")
+    }
+
+    pub fn tools_dir(&self) -> PathBuf {
+        self.root.join("tools")
+    }
+
+    /// Fraction of the filesystem holding the workspace that is in use.
+    pub fn disk_usage(&self) -> Fallible<f32> {
+        crate::utils::fs::disk_usage(&self.root)
+    }
+
+    /// Remove every build directory, including leftovers of crashed workers.
+    /// Must only be called while no worker is building.
+    pub(super) fn purge_all_build_dirs(&self) -> Fallible<()> {
+        remove_dir_all(&self.builds_dir())?;
+        fs::create_dir_all(self.builds_dir())?;
+        Ok(())
+    }
+
+    /// Remove every cached crate source; they are fetched again on demand.
+    /// Must only be called while no worker is fetching.
+    pub(super) fn purge_sources(&self) -> Fallible<()> {
+        remove_dir_all(&self.sources_dir())?;
+        fs::create_dir_all(self.sources_dir())?;
+        Ok(())
+    }
+
+    pub(super) fn build_dir(&self, name: &str) -> BuildDirectory {
+        BuildDirectory {
+            path: self.builds_dir().join(name),
+        }
+    }
+}
+
+/// Scratch directory owned by a single worker.
+///
+/// The crate source is copied into `source/` before every build so that
+/// modifications made by one toolchain (e.g. `cargo fix`) never leak into
+/// the next one, while `target/` is kept per toolchain.
+pub(super) struct BuildDirectory {
+    path: PathBuf,
+}
+
+impl BuildDirectory {
+    pub(super) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(super) fn source_dir(&self) -> PathBuf {
+        self.path.join("source")
+    }
+
+    pub(super) fn target_dir(&self, component: &str) -> PathBuf {
+        self.path.join("target").join(component)
+    }
+
+    /// Remove everything left over from previous builds.
+    pub(super) fn purge(&self) -> Fallible<()> {
+        remove_dir_all(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_workspace_layout() {
+        let dir = tempdir().unwrap();
+        let workspace = Workspace::new(dir.path()).unwrap();
+        assert!(workspace.sources_dir().is_dir());
+        assert!(workspace.cache_dir().is_dir());
+        assert!(workspace.builds_dir().is_dir());
+
+        let build_dir = workspace.build_dir("worker-0");
+        assert_eq!(build_dir.path(), dir.path().join("builds").join("worker-0"));
+        assert_eq!(
+            build_dir.target_dir("stable"),
+            dir.path().join("builds/worker-0/target/stable")
+        );
+    }
+
+    #[test]
+    fn test_build_dir_purge() {
+        let dir = tempdir().unwrap();
+        let workspace = Workspace::new(dir.path()).unwrap();
+        let build_dir = workspace.build_dir("worker-0");
+
+        fs::create_dir_all(build_dir.source_dir()).unwrap();
+        build_dir.purge().unwrap();
+        assert!(!build_dir.path().exists());
+    }
+
+    #[test]
+    fn test_purge_workspace() {
+        let dir = tempdir().unwrap();
+        let workspace = Workspace::new(dir.path()).unwrap();
+        fs::create_dir_all(workspace.build_dir("worker-0").target_dir("stable")).unwrap();
+        fs::create_dir_all(workspace.sources_dir().join("reg/serde/1.0.0")).unwrap();
+        fs::create_dir_all(workspace.cache_dir().join("crates/serde")).unwrap();
+
+        workspace.purge_all_build_dirs().unwrap();
+        workspace.purge_sources().unwrap();
+
+        assert_eq!(fs::read_dir(workspace.builds_dir()).unwrap().count(), 0);
+        assert_eq!(fs::read_dir(workspace.sources_dir()).unwrap().count(), 0);
+        // The download cache outlives the sources unpacked from it.
+        assert!(workspace.cache_dir().join("crates/serde").is_dir());
+    }
+}
@@ -326,12 +326,13 @@ mod tests {
 
         // Create an experiment first
         use crate::actions::experiments::{CreateExperiment, ExperimentActions};
-        use crate::experiments::{CrateSelect, Mode};
+        use crate::experiments::{CrateSelect, ExperimentKind, Mode};
         use crate::toolchain::Toolchain;
         use std::str::FromStr;
 
         let exp_req = CreateExperiment {
             name: "test-exp".to_string(),
+            kind: ExperimentKind::ToolchainComparison,
             toolchains: [
                 Toolchain::from_str("stable").unwrap(),
                 Toolchain::from_str("beta").unwrap(),
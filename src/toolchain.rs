@@ -1,6 +1,9 @@
 use crate::prelude::*;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::str::FromStr;
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Clone)]
@@ -15,56 +18,203 @@ pub struct Toolchain {
 }
 
 impl Toolchain {
+    /// Canonical identifier of the toolchain, under which experiments and
+    /// results store it: the full spec with its flags in a fixed order, so
+    /// toolchains differing only in their flags or patches never collide.
+    pub fn id(&self) -> String {
+        self.to_string()
+    }
+
     pub fn to_path_component(&self) -> String {
-        let mut component = self.source.to_string();
+        let mut component = self.source.to_string().replace(['/', '\\', ':'], "_");
         if let Some(ref target) = self.target {
             component.push_str("--");
             component.push_str(target);
         }
+        // Builds with different flags or patches get their own directory.
+        let plain = Toolchain {
+            source: self.source.clone(),
+            target: self.target.clone(),
+            rustflags: None,
+            rustdocflags: None,
+            cargoflags: None,
+            ci_try: false,
+            patches: vec![],
+        };
+        if *self != plain {
+            let hash = crate::utils::hex::encode(Sha256::digest(self.id()));
+            component.push_str("--");
+            component.push_str(&hash[..12]);
+        }
         component
     }
+
+    /// Check that a local toolchain is usable: its `rustc` and `cargo` exist
+    /// and the standard library of its target is installed. Other sources are
+    /// installed on demand and aren't checked here.
+    pub fn check_local(&self) -> Fallible<()> {
+        if !matches!(self.source, RustwideToolchain::Path(_) | RustwideToolchain::Link(_)) {
+            return Ok(());
+        }
+        let unusable = |reason: String| UnusableToolchain {
+            toolchain: self.to_string(),
+            reason,
+        };
+        let missing = self
+            .missing(&[])
+            .map_err(|err| unusable(format!("{:#}", err)))?;
+        if !missing.is_empty() {
+            return Err(unusable(format!("{} not installed", missing.join(", "))).into());
+        }
+        Ok(())
+    }
+
+    /// Parts of the installed toolchain missing to build for its target:
+    /// `rustc`, `cargo` and `binaries` in its `bin` directory, and the
+    /// standard library of the target. Fails if it isn't installed at all.
+    pub fn missing(&self, binaries: &[&str]) -> Fallible<Vec<String>> {
+        let sysroot = self.source.sysroot()?;
+        let mut missing = Vec::new();
+        for binary in ["rustc", "cargo"].iter().chain(binaries) {
+            let path = sysroot
+                .join("bin")
+                .join(format!("{}{}", binary, std::env::consts::EXE_SUFFIX));
+            if !path.is_file() {
+                missing.push(binary.to_string());
+            }
+        }
+        if let Some(ref target) = self.target {
+            if !sysroot.join("lib/rustlib").join(target).join("lib").is_dir() {
+                missing.push(format!("the standard library for {}", target));
+            }
+        }
+        Ok(missing)
+    }
+}
+
+/// A toolchain of an experiment that can't be used on this machine.
+#[derive(Debug, thiserror::Error)]
+#[error("toolchain {toolchain} is not usable: {reason}")]
+pub struct UnusableToolchain {
+    pub toolchain: String,
+    pub reason: String,
+}
+
+/// Whether `triple` is an OpenHarmony target.
+pub fn is_ohos_target(triple: &str) -> bool {
+    triple.ends_with("-linux-ohos")
 }
 
 impl fmt::Display for Toolchain {
+    // Printed in the format parsed by `FromStr`, as it's also how toolchains
+    // are stored in the database.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.source)?;
+        if self.ci_try {
+            write!(f, "+citry")?;
+        }
         if let Some(ref target) = self.target {
-            write!(f, " (target: {})", target)?;
+            write!(f, "+target={}", target)?;
+        }
+        if let Some(ref flags) = self.rustflags {
+            write!(f, "+rustflags={}", flags)?;
+        }
+        if let Some(ref flags) = self.rustdocflags {
+            write!(f, "+rustdocflags={}", flags)?;
+        }
+        if let Some(ref flags) = self.cargoflags {
+            write!(f, "+cargoflags={}", flags)?;
+        }
+        for patch in &self.patches {
+            write!(f, "+patch={}", patch)?;
         }
         Ok(())
     }
 }
 
+/// Flags accepted after the source of a toolchain spec.
+const FLAGS: &[&str] = &["citry", "target", "rustflags", "rustdocflags", "cargoflags", "patch"];
+
+/// Split a toolchain spec at every `+` starting a flag, leaving alone the ones
+/// inside flag values, as in `rustflags=-Ctarget-feature=+crt-static`.
+fn split_flags(input: &str) -> Vec<&str> {
+    let starts_flag = |rest: &str| {
+        FLAGS.iter().any(|flag| match rest.strip_prefix(flag) {
+            Some(after) if *flag == "citry" => after.is_empty() || after.starts_with('+'),
+            Some(after) => after.starts_with('='),
+            None => false,
+        })
+    };
+
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (idx, _) in input.match_indices('+') {
+        if starts_flag(&input[idx + 1..]) {
+            parts.push(&input[start..idx]);
+            start = idx + 1;
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
 impl FromStr for Toolchain {
     type Err = Error;
 
+    // Format: "<source>[+citry][+target=<triple>][+rustflags=<flags>]
+    // [+rustdocflags=<flags>][+cargoflags=<flags>][+patch=<name>=<repo>#<branch>]*"
     fn from_str(input: &str) -> Fallible<Self> {
-        // Parse format: "stable+target=aarch64-unknown-linux-gnu"
-        let mut parts = input.splitn(2, '+');
+        let mut parts = split_flags(input).into_iter();
         let source_str = parts.next().unwrap();
-        let source = RustwideToolchain::from_str(source_str)?;
-        
-        let mut target = None;
-        if let Some(flags) = parts.next() {
-            for flag in flags.split('+') {
-                if let Some(target_value) = flag.strip_prefix("target=") {
-                    target = Some(target_value.to_string());
-                }
-            }
+        if source_str.contains('+') {
+            anyhow::bail!("invalid toolchain flag in {}", input);
         }
 
-        Ok(Toolchain {
-            source,
-            target,
+        let mut toolchain = Toolchain {
+            source: RustwideToolchain::from_str(source_str)?,
+            target: None,
             rustflags: None,
             rustdocflags: None,
             cargoflags: None,
             ci_try: false,
             patches: vec![],
-        })
+        };
+
+        for flag in parts {
+            if flag == "citry" {
+                if toolchain.ci_try {
+                    anyhow::bail!("duplicate toolchain flag citry in {}", input);
+                }
+                toolchain.ci_try = true;
+                continue;
+            }
+
+            let (name, value) = flag.split_once('=').unwrap();
+            if value.is_empty() {
+                anyhow::bail!("empty value for toolchain flag {} in {}", name, input);
+            }
+            let slot = match name {
+                "target" => &mut toolchain.target,
+                "rustflags" => &mut toolchain.rustflags,
+                "rustdocflags" => &mut toolchain.rustdocflags,
+                "cargoflags" => &mut toolchain.cargoflags,
+                "patch" => {
+                    toolchain.patches.push(value.parse()?);
+                    continue;
+                }
+                _ => unreachable!(),
+            };
+            if slot.replace(value.to_string()).is_some() {
+                anyhow::bail!("duplicate toolchain flag {} in {}", name, input);
+            }
+        }
+
+        Ok(toolchain)
     }
 }
 
+/// Dependency overridden with a branch of a git repository while building
+/// with a toolchain, written `<name>=<repo>#<branch>` in toolchain specs.
 #[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Clone)]
 pub struct CratePatch {
     pub name: String,
@@ -72,6 +222,33 @@ pub struct CratePatch {
     pub branch: String,
 }
 
+impl fmt::Display for CratePatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}#{}", self.name, self.repo, self.branch)
+    }
+}
+
+impl FromStr for CratePatch {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Fallible<Self> {
+        let invalid = || anyhow::anyhow!("invalid crate patch {}, expected <name>=<repo>#<branch>", input);
+        let (name, rest) = input.split_once('=').ok_or_else(invalid)?;
+        let (repo, branch) = rest.rsplit_once('#').ok_or_else(invalid)?;
+        if name.is_empty() || branch.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(CratePatch {
+            name: name.to_string(),
+            repo: repo
+                .parse()
+                .with_context(|| format!("invalid repository in crate patch {}", input))?,
+            branch: branch.to_string(),
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub enum RustwideToolchain {
@@ -79,6 +256,60 @@ pub enum RustwideToolchain {
     Master { sha: Option<String> },
     Try { sha: String },
     CI { sha: String, alt: bool },
+    /// Toolchain installed in a local directory, written `path:<dir>`.
+    Path(PathBuf),
+    /// Toolchain registered with `rustup toolchain link`, written `link:<name>`.
+    Link(String),
+}
+
+impl RustwideToolchain {
+    /// Name under which the toolchain is installed in rustup, as used in
+    /// `cargo +<name>`. Toolchains installed from CI artifacts are named
+    /// after their commit, following `rustup-toolchain-install-master`.
+    /// Toolchains in a local directory aren't known to rustup.
+    pub fn rustup_name(&self) -> Option<String> {
+        match self {
+            RustwideToolchain::Dist(name) => Some(name.clone()),
+            RustwideToolchain::Master { sha: Some(sha) } => Some(sha.clone()),
+            RustwideToolchain::Master { sha: None } => Some("master".to_string()),
+            RustwideToolchain::Try { sha } => Some(sha.clone()),
+            RustwideToolchain::CI { sha, alt: false } => Some(sha.clone()),
+            RustwideToolchain::CI { sha, alt: true } => Some(format!("{}-alt", sha)),
+            RustwideToolchain::Path(_) => None,
+            RustwideToolchain::Link(name) => Some(name.clone()),
+        }
+    }
+
+    /// Directory of a toolchain that is run directly instead of through rustup.
+    pub fn install_dir(&self) -> Option<&Path> {
+        match self {
+            RustwideToolchain::Path(dir) => Some(dir),
+            _ => None,
+        }
+    }
+
+    /// Root of the installed toolchain, failing if it isn't installed.
+    pub fn sysroot(&self) -> Fallible<PathBuf> {
+        let name = match (self.install_dir(), self.rustup_name()) {
+            (Some(dir), _) => return Ok(dir.to_path_buf()),
+            (None, Some(name)) => name,
+            (None, None) => unreachable!("toolchains outside of rustup have a directory"),
+        };
+        let output = Command::new("rustc")
+            .arg(format!("+{}", name))
+            .args(["--print", "sysroot"])
+            .env("RUSTUP_AUTO_INSTALL", "0")
+            .output()
+            .context("failed to run rustc")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "{} is not installed in rustup: {}",
+                name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(PathBuf::from(String::from_utf8(output.stdout)?.trim()))
+    }
 }
 
 impl fmt::Display for RustwideToolchain {
@@ -90,6 +321,8 @@ impl fmt::Display for RustwideToolchain {
             RustwideToolchain::Try { sha } => write!(f, "try#{}", sha),
             RustwideToolchain::CI { sha, alt: false } => write!(f, "ci#{}", sha),
             RustwideToolchain::CI { sha, alt: true } => write!(f, "ci-alt#{}", sha),
+            RustwideToolchain::Path(dir) => write!(f, "path:{}", dir.display()),
+            RustwideToolchain::Link(name) => write!(f, "link:{}", name),
         }
     }
 }
@@ -98,6 +331,21 @@ impl FromStr for RustwideToolchain {
     type Err = Error;
 
     fn from_str(input: &str) -> Fallible<Self> {
+        // Directories and link names may contain '#'.
+        if let Some(dir) = input.strip_prefix("path:") {
+            let dir = PathBuf::from(dir);
+            if !dir.is_absolute() {
+                anyhow::bail!("the directory of a path toolchain must be absolute: {}", input);
+            }
+            return Ok(RustwideToolchain::Path(dir));
+        }
+        if let Some(name) = input.strip_prefix("link:") {
+            if name.is_empty() {
+                anyhow::bail!("missing name of linked toolchain: {}", input);
+            }
+            return Ok(RustwideToolchain::Link(name.to_string()));
+        }
+
         if let Some(hash_idx) = input.find('#') {
             let (prefix, sha) = input.split_at(hash_idx);
             let sha = &sha[1..]; // Remove the '#'
@@ -178,6 +426,80 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_rustup_name() {
+        assert_eq!(
+            RustwideToolchain::Dist("stable".to_string()).rustup_name().as_deref(),
+            Some("stable")
+        );
+        assert_eq!(
+            RustwideToolchain::Try {
+                sha: "abc123".to_string()
+            }
+            .rustup_name()
+            .as_deref(),
+            Some("abc123")
+        );
+        assert_eq!(
+            RustwideToolchain::CI {
+                sha: "abc123".to_string(),
+                alt: true
+            }
+            .rustup_name()
+            .as_deref(),
+            Some("abc123-alt")
+        );
+        assert_eq!(
+            RustwideToolchain::Link("ohos".to_string()).rustup_name().as_deref(),
+            Some("ohos")
+        );
+        assert_eq!(RustwideToolchain::Path("/opt/rust".into()).rustup_name(), None);
+    }
+
+    #[test]
+    fn test_local_toolchain_parsing() {
+        let tc = "path:/opt/rust-ohos-1.80+target=aarch64-unknown-linux-ohos"
+            .parse::<Toolchain>()
+            .unwrap();
+        assert_eq!(tc.source, RustwideToolchain::Path("/opt/rust-ohos-1.80".into()));
+        assert_eq!(tc.source.install_dir(), Some(Path::new("/opt/rust-ohos-1.80")));
+        assert_eq!(tc.to_string(), "path:/opt/rust-ohos-1.80+target=aarch64-unknown-linux-ohos");
+        assert_eq!(tc.to_path_component(), "path__opt_rust-ohos-1.80--aarch64-unknown-linux-ohos");
+
+        let tc = "link:ohos#2".parse::<Toolchain>().unwrap();
+        assert_eq!(tc.source, RustwideToolchain::Link("ohos#2".to_string()));
+        assert_eq!(tc.to_string(), "link:ohos#2");
+
+        assert!("path:relative/dir".parse::<Toolchain>().is_err());
+        assert!("link:".parse::<Toolchain>().is_err());
+    }
+
+    #[test]
+    fn test_check_local_toolchain() {
+        let dir = tempfile::tempdir().unwrap();
+        let spec = format!("path:{}+target=aarch64-unknown-linux-ohos", dir.path().display());
+        let tc = spec.parse::<Toolchain>().unwrap();
+
+        let err = tc.check_local().unwrap_err();
+        assert!(err.is::<UnusableToolchain>());
+
+        std::fs::create_dir_all(dir.path().join("bin")).unwrap();
+        for tool in ["rustc", "cargo"] {
+            let name = format!("{}{}", tool, std::env::consts::EXE_SUFFIX);
+            std::fs::write(dir.path().join("bin").join(name), "").unwrap();
+        }
+        let err = tc.check_local().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("the standard library for aarch64-unknown-linux-ohos not installed"));
+
+        std::fs::create_dir_all(dir.path().join("lib/rustlib/aarch64-unknown-linux-ohos/lib")).unwrap();
+        tc.check_local().unwrap();
+
+        // Dist toolchains are installed on demand.
+        "stable".parse::<Toolchain>().unwrap().check_local().unwrap();
+    }
+
     #[test]
     fn test_toolchain_display() {
         let tc = Toolchain {
@@ -200,7 +522,8 @@ mod tests {
             ci_try: false,
             patches: vec![],
         };
-        assert_eq!(tc.to_string(), "stable (target: x86_64-unknown-linux-gnu)");
+        assert_eq!(tc.to_string(), "stable+target=x86_64-unknown-linux-gnu");
+        assert_eq!(tc.to_string().parse::<Toolchain>().unwrap(), tc);
     }
 
     #[test]
@@ -222,4 +545,78 @@ mod tests {
         assert_eq!(tc.source, RustwideToolchain::Dist("stable".to_string()));
         assert_eq!(tc.target, Some("aarch64-unknown-linux-gnu".to_string()));
     }
+
+    #[test]
+    fn test_toolchain_full_spec() {
+        let spec = "nightly+target=aarch64-unknown-linux-ohos\
+                    +rustflags=-Cpanic=abort -Ctarget-feature=+crt-static\
+                    +cargoflags=-Zbuild-std\
+                    +patch=libc=https://gitee.com/openharmony/libc#ohos";
+        let tc = spec.parse::<Toolchain>().unwrap();
+        assert_eq!(tc.target.as_deref(), Some("aarch64-unknown-linux-ohos"));
+        assert_eq!(
+            tc.rustflags.as_deref(),
+            Some("-Cpanic=abort -Ctarget-feature=+crt-static")
+        );
+        assert_eq!(tc.rustdocflags, None);
+        assert_eq!(tc.cargoflags.as_deref(), Some("-Zbuild-std"));
+        assert_eq!(
+            tc.patches,
+            vec![CratePatch {
+                name: "libc".to_string(),
+                repo: "https://gitee.com/openharmony/libc".parse().unwrap(),
+                branch: "ohos".to_string(),
+            }]
+        );
+        assert_eq!(tc.to_string(), spec);
+
+        for spec in [
+            "stable+citry",
+            "ci#abc123+citry+target=x86_64-unknown-linux-gnu",
+            "beta+rustdocflags=--cfg docsrs",
+            "stable+patch=libc=https://example.com/libc#main+patch=nix=https://example.com/nix#ohos",
+        ] {
+            assert_eq!(spec.parse::<Toolchain>().unwrap().to_string(), spec);
+        }
+    }
+
+    #[test]
+    fn test_toolchain_id() {
+        let plain: Toolchain = "stable+target=aarch64-unknown-linux-ohos".parse().unwrap();
+        let flags: Toolchain = "stable+rustflags=-Copt-level=1+target=aarch64-unknown-linux-ohos"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            flags.id(),
+            "stable+target=aarch64-unknown-linux-ohos+rustflags=-Copt-level=1"
+        );
+        assert_ne!(plain.id(), flags.id());
+
+        assert_eq!(plain.to_path_component(), "stable--aarch64-unknown-linux-ohos");
+        assert!(flags
+            .to_path_component()
+            .starts_with("stable--aarch64-unknown-linux-ohos--"));
+        assert_ne!(plain.to_path_component(), flags.to_path_component());
+    }
+
+    #[test]
+    fn test_is_ohos_target() {
+        assert!(is_ohos_target("aarch64-unknown-linux-ohos"));
+        assert!(!is_ohos_target("aarch64-unknown-linux-gnu"));
+    }
+
+    #[test]
+    fn test_toolchain_invalid_spec() {
+        for spec in [
+            "stable+foo=bar",
+            "stable+target=",
+            "stable+target=a+target=b",
+            "stable+citry+citry",
+            "stable+patch=libc",
+            "stable+patch=libc=https://example.com/libc",
+            "stable+patch=libc=not a url#main",
+        ] {
+            assert!(spec.parse::<Toolchain>().is_err(), "{}", spec);
+        }
+    }
 }
@@ -0,0 +1,102 @@
+use crate::prelude::*;
+use std::fs;
+use std::path::Path;
+
+/// Recursively copy the contents of `src` into `dest`, creating `dest` if needed.
+pub fn copy_dir(src: &Path, dest: &Path) -> Fallible<()> {
+    fs::create_dir_all(dest)
+        .with_context(|| format!("failed to create {}", dest.display()))?;
+
+    for entry in fs::read_dir(src).with_context(|| format!("failed to read {}", src.display()))? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let target = dest.join(entry.file_name());
+
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else if cfg!(unix) && file_type.is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &target)?;
+        } else {
+            fs::copy(entry.path(), &target).with_context(|| {
+                format!("failed to copy {} to {}", entry.path().display(), target.display())
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove a directory and everything inside it, ignoring missing directories.
+pub fn remove_dir_all(path: &Path) -> Fallible<()> {
+    match fs::remove_dir_all(path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(Error::from(err).context(format!("failed to remove {}", path.display()))),
+    }
+}
+
+/// Fraction (between 0 and 1) of the filesystem holding `path` that is in
+/// use, computed the same way as `df`: blocks reserved for root count as
+/// unavailable rather than free.
+#[cfg(unix)]
+pub fn disk_usage(path: &Path) -> Fallible<f32> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is a valid NUL-terminated string and `stat` is a
+    // properly sized buffer for statvfs to fill.
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(Error::from(std::io::Error::last_os_error())
+            .context(format!("failed to stat filesystem of {}", path.display())));
+    }
+
+    let used = stat.f_blocks.saturating_sub(stat.f_bfree) as f64;
+    let available = stat.f_bavail as f64;
+    if used + available == 0.0 {
+        return Ok(0.0);
+    }
+    Ok((used / (used + available)) as f32)
+}
+
+#[cfg(not(unix))]
+pub fn disk_usage(_path: &Path) -> Fallible<f32> {
+    anyhow::bail!("measuring disk usage is only supported on unix")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_copy_dir() {
+        let src = tempdir().unwrap();
+        fs::create_dir_all(src.path().join("nested")).unwrap();
+        fs::write(src.path().join("a.txt"), "a").unwrap();
+        fs::write(src.path().join("nested/b.txt"), "b").unwrap();
+
+        let dest = tempdir().unwrap();
+        let dest = dest.path().join("copy");
+        copy_dir(src.path(), &dest).unwrap();
+
+        assert_eq!(fs::read_to_string(dest.join("a.txt")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(dest.join("nested/b.txt")).unwrap(), "b");
+    }
+
+    #[test]
+    fn test_remove_missing_dir() {
+        let dir = tempdir().unwrap();
+        assert!(remove_dir_all(&dir.path().join("missing")).is_ok());
+    }
+
+    #[test]
+    fn test_disk_usage() {
+        let dir = tempdir().unwrap();
+        let usage = disk_usage(dir.path()).unwrap();
+        assert!((0.0..=1.0).contains(&usage));
+        assert!(disk_usage(&dir.path().join("missing")).is_err());
+    }
+}
@@ -27,7 +27,7 @@ mod tests {
     fn test_encode() {
         assert_eq!(encode(b"hello"), "68656c6c6f");
         assert_eq!(encode(b""), "");
-        assert_eq!(encode(&[0xff, 0x00, 0xaa]), "ff00aa");
+        assert_eq!(encode([0xff, 0x00, 0xaa]), "ff00aa");
     }
 
     #[test]
@@ -27,6 +27,17 @@ impl HttpClient {
         Ok(body)
     }
 
+    pub fn get_bytes(&self, url: &str) -> Fallible<Vec<u8>> {
+        let response = self.client.get(url).send()?;
+        let status = response.status();
+
+        if !status.is_success() {
+            anyhow::bail!("HTTP GET failed: {} - {}", status, url);
+        }
+
+        Ok(response.bytes()?.to_vec())
+    }
+
     pub fn post_json(&self, url: &str, json: &serde_json::Value) -> Fallible<String> {
         let response = self.client.post(url).json(json).send()?;
         let status = response.status();
@@ -1,3 +1,4 @@
+pub mod fs;
 pub mod hex;
 pub mod http;
 pub mod size;
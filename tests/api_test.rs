@@ -1,11 +1,12 @@
 use crater_ohos::actions::experiments::{CreateExperiment, ExperimentActions};
 use crater_ohos::db::Database;
-use crater_ohos::experiments::{CrateSelect, Mode};
+use crater_ohos::experiments::{CrateSelect, ExperimentKind, Mode};
 use crater_ohos::server::tokens::{Permission, TokenManager};
 use crater_ohos::toolchain::Toolchain;
 use std::str::FromStr;
 
 #[test]
+#[allow(unused_imports, clippy::assertions_on_constants)]
 fn test_api_module_exists() {
     // Simple test to verify the API module compiles and is accessible
     use crater_ohos::api;
@@ -21,6 +22,7 @@ async fn test_experiment_creation_for_api() {
     // Create an experiment (this would be done via API in real usage)
     let req = CreateExperiment {
         name: "test-api-experiment".to_string(),
+        kind: ExperimentKind::ToolchainComparison,
         toolchains: [
             Toolchain::from_str("stable").unwrap(),
             Toolchain::from_str("beta").unwrap(),
@@ -83,7 +85,53 @@ async fn test_agent_registration_for_api() {
     assert_eq!(agents.len(), 1);
 }
 
+#[tokio::test]
+async fn test_file_crate_select_rejected_by_api() {
+    use axum::extract::{Path, State};
+    use axum::Json;
+    use crater_ohos::api::error::ApiError;
+    use crater_ohos::api::routes::experiments::{
+        create_experiment, edit_experiment, CreateExperimentRequest, EditExperimentRequest,
+    };
+    use std::sync::Arc;
+
+    let db = Arc::new(Database::temp().unwrap());
+    let req = |crate_select: &str| CreateExperimentRequest {
+        name: "test-api-experiment".to_string(),
+        kind: None,
+        toolchains: ["stable".to_string(), "beta".to_string()],
+        mode: "build-and-test".to_string(),
+        crate_select: crate_select.to_string(),
+        platform_issue: None,
+        callback_url: None,
+        priority: 0,
+    };
+
+    // The server never reads crate lists from its own filesystem.
+    let res = create_experiment(State(db.clone()), Json(req("file:/etc/passwd"))).await;
+    assert!(matches!(res, Err(ApiError::BadRequest(_))));
+    assert!(db.list().unwrap().is_empty());
+
+    let _ = create_experiment(State(db.clone()), Json(req("demo"))).await.unwrap();
+    let edit = EditExperimentRequest {
+        name: None,
+        mode: None,
+        crate_select: Some("file:/etc/passwd".to_string()),
+        platform_issue: None,
+        callback_url: None,
+        priority: None,
+    };
+    let res = edit_experiment(
+        State(db.clone()),
+        Path("test-api-experiment".to_string()),
+        Json(edit),
+    )
+    .await;
+    assert!(matches!(res, Err(ApiError::BadRequest(_))));
+}
+
 #[test]
+#[allow(unused_imports, clippy::assertions_on_constants)]
 fn test_cli_module_exists() {
     // Simple test to verify the CLI module compiles and is accessible
     use crater_ohos::cli;
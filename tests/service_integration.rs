@@ -1,6 +1,6 @@
 use crater_ohos::actions::experiments::{CreateExperiment, EditExperiment, ExperimentActions};
 use crater_ohos::db::Database;
-use crater_ohos::experiments::{CrateSelect, Mode, PlatformIssue, Status};
+use crater_ohos::experiments::{CrateSelect, ExperimentKind, Mode, PlatformIssue, Status};
 use crater_ohos::server::agents::{AgentManager, RegisterAgent};
 use crater_ohos::server::callback::{CallbackEvent, CallbackPayload, CallbackService};
 use crater_ohos::server::tokens::{Permission, TokenManager};
@@ -15,6 +15,7 @@ fn test_experiment_workflow() {
     // Create experiment
     let req = CreateExperiment {
         name: "integration-test".to_string(),
+        kind: ExperimentKind::ToolchainComparison,
         toolchains: [
             Toolchain::from_str("stable").unwrap(),
             Toolchain::from_str("beta").unwrap(),
@@ -73,6 +74,7 @@ fn test_agent_task_assignment() {
     // Create experiment
     let exp_req = CreateExperiment {
         name: "agent-test-exp".to_string(),
+        kind: ExperimentKind::ToolchainComparison,
         toolchains: [
             Toolchain::from_str("stable").unwrap(),
             Toolchain::from_str("beta").unwrap(),
@@ -189,6 +191,7 @@ fn test_full_lifecycle_with_agents_and_tokens() {
     let exp = db
         .create(CreateExperiment {
             name: "full-test".to_string(),
+            kind: ExperimentKind::ToolchainComparison,
             toolchains: [
                 Toolchain::from_str("stable").unwrap(),
                 Toolchain::from_str("beta").unwrap(),